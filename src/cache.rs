@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use crate::error::VelocityError;
+use crate::token::token_parse::Tokenizer;
+
+/// 默认最多缓存的模板数量
+pub const DEFAULT_CACHE_CAPACITY: usize = 512;
+
+/// 模板解析结果的缓存策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// 不缓存，每次渲染都重新解析
+    Disabled,
+    /// 缓存所有解析过的模板，适合模板数量固定的场景
+    Unbounded,
+    /// 最多缓存指定数量的模板，超出时淘汰最久未使用的模板（默认）
+    Lru(usize),
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy::Lru(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+/// 缓存命中情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// 当前缓存的模板数量
+    pub entries: usize,
+}
+
+/// 模板源码及其语法树
+pub(crate) type Compiled = (Arc<str>, Arc<Vec<Tokenizer>>);
+
+#[derive(Debug)]
+struct Entry {
+    compiled: Compiled,
+    // 通过加载器读取的模板的修改时间，变化后缓存失效
+    modified: Option<SystemTime>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    // 按 last_used 排序的键，淘汰时取第一个
+    order: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// 每个 VelocityEngine 各自持有的语法树缓存。模板字符串以内容的 md5 为键，
+/// 通过加载器读取的模板以名称为键，并在修改时间变化时重新加载
+#[derive(Debug, Default)]
+pub(crate) struct TemplateCache {
+    policy: CachePolicy,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TemplateCache {
+    pub(crate) fn new(policy: CachePolicy) -> Self {
+        TemplateCache {
+            policy,
+            ..TemplateCache::default()
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !matches!(self.policy, CachePolicy::Disabled | CachePolicy::Lru(0))
+    }
+
+    /// 返回模板字符串对应的语法树，没有时调用 parse 解析并放入缓存
+    pub(crate) fn get_or_parse<F>(&self, source: &str, parse: F) -> Result<Arc<Vec<Tokenizer>>, VelocityError>
+    where
+        F: FnOnce() -> Result<Vec<Tokenizer>, VelocityError>,
+    {
+        if !self.enabled() {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return parse().map(Arc::new);
+        }
+
+        // md5 可以构造碰撞，命中时还要比较缓存的源码
+        let key = format!("md5:{:x}", md5::compute(source));
+        if let Some((_, tokens)) = self.get(&key, |entry| &*entry.compiled.0 == source) {
+            return Ok(tokens);
+        }
+
+        // 解析时不持有锁
+        let tokens = Arc::new(parse()?);
+        self.insert(key, (Arc::from(source), tokens.clone()), None);
+        Ok(tokens)
+    }
+
+    /// 返回加载器中名为 name 的模板，修改时间与缓存时不同则调用 load 重新加载
+    pub(crate) fn get_or_load<F>(&self, name: &str, modified: Option<SystemTime>, load: F) -> Result<Compiled, VelocityError>
+    where
+        F: FnOnce() -> Result<Compiled, VelocityError>,
+    {
+        if !self.enabled() {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return load();
+        }
+
+        let key = format!("name:{}", name);
+        if let Some(compiled) = self.get(&key, |entry| entry.modified == modified) {
+            return Ok(compiled);
+        }
+
+        let compiled = load()?;
+        self.insert(key, compiled.clone(), modified);
+        Ok(compiled)
+    }
+
+    /// 缓存中 key 对应且 valid 的语法树
+    fn get(&self, key: &str, valid: impl FnOnce(&Entry) -> bool) -> Option<Compiled> {
+        let mut entries = self.entries.lock().unwrap();
+        let clock = entries.tick();
+
+        let compiled = match entries.map.get_mut(key) {
+            Some(entry) if valid(entry) => {
+                let last_used = std::mem::replace(&mut entry.last_used, clock);
+                let compiled = entry.compiled.clone();
+                entries.order.remove(&last_used);
+                entries.order.insert(clock, key.to_string());
+                Some(compiled)
+            }
+            _ => None,
+        };
+        match compiled {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        compiled
+    }
+
+    fn insert(&self, key: String, compiled: Compiled, modified: Option<SystemTime>) {
+        let mut entries = self.entries.lock().unwrap();
+        let last_used = entries.tick();
+        entries.order.insert(last_used, key.clone());
+        if let Some(replaced) = entries.map.insert(key, Entry { compiled, modified, last_used }) {
+            entries.order.remove(&replaced.last_used);
+        }
+
+        if let CachePolicy::Lru(capacity) = self.policy {
+            while entries.map.len() > capacity {
+                match entries.order.pop_first() {
+                    Some((_, oldest)) => entries.map.remove(&oldest),
+                    None => break,
+                };
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().map.len(),
+        }
+    }
+
+    /// 清空缓存，统计数据保留
+    pub(crate) fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.map.clear();
+        entries.order.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_collision_test() {
+        // 模拟 md5 碰撞：键相同但源码不同时重新解析
+        let cache = TemplateCache::new(CachePolicy::default());
+        let key = format!("md5:{:x}", md5::compute("a"));
+        cache.insert(key, (Arc::from("b"), Arc::new(Vec::new())), None);
+
+        let tokens = cache.get_or_parse("a", || Ok(vec![Tokenizer::Stop { span: Default::default() }])).unwrap();
+        assert_eq!(tokens.len(), 1);
+        let tokens = cache.get_or_parse("a", || unreachable!()).unwrap();
+        assert_eq!(tokens.len(), 1);
+    }
+
+    #[test]
+    fn lru_order_test() {
+        let cache = TemplateCache::new(CachePolicy::Lru(2));
+        let parse = || Ok(Vec::new());
+        cache.get_or_parse("a", parse).unwrap();
+        cache.get_or_parse("b", parse).unwrap();
+        // 使用 a 之后淘汰 b
+        cache.get_or_parse("a", || unreachable!()).unwrap();
+        cache.get_or_parse("c", parse).unwrap();
+        cache.get_or_parse("a", || unreachable!()).unwrap();
+        cache.get_or_parse("c", || unreachable!()).unwrap();
+
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.map.len(), 2);
+        assert_eq!(entries.order.len(), 2);
+        for (last_used, key) in &entries.order {
+            assert_eq!(entries.map[key].last_used, *last_used);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::event::EventHandlers;
+use crate::expression::expr_eval::DEFAULT_MAX_RANGE_LENGTH;
+use crate::loader::{FileResourceLoader, ResourceLoader};
+
+/// 指令周围空白的处理方式，与 Apache Velocity 2 的 `parser.space_gobbling` 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SpaceGobbling {
+    /// 不处理任何空白
+    None,
+    /// 兼容 Velocity 1.x：去掉指令后面的空白和换行符，`#set` 单独占一行时还会去掉行首缩进
+    Bc,
+    /// 指令单独占一行时，去掉该行的缩进和换行符（默认）
+    #[default]
+    Lines,
+    /// 在 Lines 的基础上，再去掉块内容相对于块指令多出来的缩进
+    Structured,
+}
+
+impl FromStr for SpaceGobbling {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(SpaceGobbling::None),
+            "bc" => Ok(SpaceGobbling::Bc),
+            "lines" => Ok(SpaceGobbling::Lines),
+            "structured" => Ok(SpaceGobbling::Structured),
+            _ => Err(format!("unknown space gobbling mode '{}'", value)),
+        }
+    }
+}
+
+/// `#set` 写入变量的位置，与 Apache Velocity 的 `velocimacro.context.localscope` 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SetScope {
+    /// 修改循环变量、宏参数等已有的局部变量，其他变量写入模板上下文，循环或宏调用结束后仍然可见（默认）
+    #[default]
+    Global,
+    /// 宏内部的 `#set` 写入宏调用的局部帧，宏调用结束后丢弃
+    Local,
+}
+
+/// 引用输出时的自动转义方式，模板中的普通文本和 `#include` 的内容不转义
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Escape {
+    /// 不转义（默认）
+    #[default]
+    None,
+    /// `& < > " '` 转成 HTML 实体
+    Html,
+    /// 与 Html 相同，单引号转成 `&apos;`
+    Xml,
+    /// 作为 JSON 字符串的内容转义
+    Json,
+    /// 作为 JavaScript 字符串的内容转义
+    JavaScript,
+    /// 单引号和 `\` 都写两次，用于 SQL 字符串；适用于 MySQL 等把 `\` 当作转义符的数据库，
+    /// 标准 SQL 中 `\` 不是转义符，写两次后字符串中会多出一个 `\`
+    Sql,
+}
+
+impl FromStr for Escape {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Escape::None),
+            "html" => Ok(Escape::Html),
+            "xml" => Ok(Escape::Xml),
+            "json" => Ok(Escape::Json),
+            "javascript" | "js" => Ok(Escape::JavaScript),
+            "sql" => Ok(Escape::Sql),
+            _ => Err(format!("unknown escape mode '{}'", value)),
+        }
+    }
+}
+
+/// 渲染配置
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub space_gobbling: SpaceGobbling,
+    /// 宏调用（包括递归调用）的最大嵌套深度
+    pub max_macro_depth: usize,
+    /// `#parse`、`#include` 使用的模板加载器，默认从当前目录读取文件
+    pub loader: Arc<dyn ResourceLoader>,
+    /// `#parse` 的最大嵌套深度
+    pub max_include_depth: usize,
+    /// `[1..$n]` 计算为数组时最多包含的元素个数；`#foreach` 直接循环范围时不受限制
+    pub max_range_length: usize,
+    /// 严格模式：引用未定义、属性路径中间为 null、`#if` 的条件不是布尔值或表达式有误时渲染失败
+    pub strict: bool,
+    /// 引用输出、无效引用、`#parse`/`#include` 以及方法出错时调用的事件处理器
+    pub events: EventHandlers,
+    /// 引用输出时的自动转义方式，可以通过 `Template::with_escape` 为单个模板修改
+    pub escape: Escape,
+    pub set_scope: SetScope,
+    /// 除了 `$foreach.index` 等，还写入 `$item.index` 这类以元素名开头的循环变量（旧写法），默认关闭
+    pub element_loop_variables: bool,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            space_gobbling: SpaceGobbling::default(),
+            max_macro_depth: 20,
+            loader: Arc::new(FileResourceLoader::default()),
+            max_include_depth: 10,
+            max_range_length: DEFAULT_MAX_RANGE_LENGTH,
+            strict: false,
+            events: EventHandlers::default(),
+            escape: Escape::default(),
+            set_scope: SetScope::default(),
+            element_loop_variables: false,
+        }
+    }
+}
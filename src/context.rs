@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use crate::config::SetScope;
+
+/// 按名称查找变量，表达式既可以在 HashMap 中计算，也可以在渲染时的 Context 中计算
+pub trait Variables {
+    fn get(&self, key: &str) -> Option<&Value>;
+}
+
+impl Variables for HashMap<String, Value> {
+    fn get(&self, key: &str) -> Option<&Value> {
+        HashMap::get(self, key)
+    }
+}
+
+/// 局部帧的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Foreach,
+    Macro,
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+    variables: HashMap<String, Value>,
+    safe: HashSet<String>,
+}
+
+/// 渲染上下文，从外到内分为三层：引擎的工具变量（只读）、调用者传入的模板变量、
+/// `#foreach` 和宏调用压入的局部帧。查找时从最内层开始，局部帧在循环或宏调用结束时弹出。
+/// 调用者的变量只读时（`Template::render`），`#set` 写入它上面的一个空的模板变量层，不复制调用者的变量。
+/// 每一层还记录哪些变量的值已经转义过（例如 `#set($a = $b.safe())`、`$bodyContent`），输出时不再自动转义
+#[derive(Debug)]
+pub struct Context<'a> {
+    globals: &'a HashMap<String, Value>,
+    shared: Option<&'a HashMap<String, Value>>,
+    template: &'a mut HashMap<String, Value>,
+    /// 模板变量中已经转义过的变量
+    safe: HashSet<String>,
+    frames: Vec<Frame>,
+    set_scope: SetScope,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(globals: &'a HashMap<String, Value>, template: &'a mut HashMap<String, Value>, set_scope: SetScope) -> Self {
+        Context {
+            globals,
+            shared: None,
+            template,
+            safe: HashSet::new(),
+            frames: Vec::new(),
+            set_scope,
+        }
+    }
+
+    /// 调用者的变量 shared 只读，`#set` 写入 template
+    pub fn layered(
+        globals: &'a HashMap<String, Value>,
+        shared: &'a HashMap<String, Value>,
+        template: &'a mut HashMap<String, Value>,
+        set_scope: SetScope,
+    ) -> Self {
+        Context {
+            shared: Some(shared),
+            ..Context::new(globals, template, set_scope)
+        }
+    }
+
+    pub fn push(&mut self, kind: FrameKind) {
+        self.frames.push(Frame {
+            kind,
+            variables: HashMap::new(),
+            safe: HashSet::new(),
+        });
+    }
+
+    pub fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    /// 在最内层的帧中定义变量，例如循环变量、宏参数；没有局部帧时写入模板变量
+    pub fn define(&mut self, key: impl Into<String>, value: Value) {
+        self.define_marked(key.into(), value, false);
+    }
+
+    /// 与 define 相同，变量的值已经转义过
+    pub fn define_safe(&mut self, key: impl Into<String>, value: Value) {
+        self.define_marked(key.into(), value, true);
+    }
+
+    fn define_marked(&mut self, key: String, value: Value, safe: bool) {
+        let frame = self.frames.len().checked_sub(1);
+        self.mark(frame, &key, safe);
+        match frame {
+            Some(index) => {
+                self.frames[index].variables.insert(key, value);
+            }
+            None => {
+                self.template.insert(key, value);
+            }
+        }
+    }
+
+    /// `#set` 赋值：修改局部帧中已有的变量（例如宏参数）；否则 SetScope::Local 时写入最内层的宏调用帧，
+    /// 其他情况写入模板变量。局部帧或只读的调用者变量中有同名变量时，赋值为 null 保留 null，以免露出外层的变量
+    pub fn set(&mut self, key: &str, value: Value) {
+        self.set_marked(key, value, false);
+    }
+
+    /// 与 set 相同，变量的值已经转义过
+    pub fn set_safe(&mut self, key: &str, value: Value) {
+        self.set_marked(key, value, true);
+    }
+
+    fn set_marked(&mut self, key: &str, value: Value, safe: bool) {
+        let local = self.set_scope == SetScope::Local;
+        let frame = match self.frames.iter().rposition(|frame| frame.variables.contains_key(key)) {
+            Some(index) => Some(index),
+            None if local => self.frames.iter().rposition(|frame| frame.kind == FrameKind::Macro),
+            None => None,
+        };
+        self.mark(frame, key, safe && !value.is_null());
+        match frame {
+            Some(index) => {
+                self.frames[index].variables.insert(key.to_string(), value);
+            }
+            None if value.is_null() && !self.shared.is_some_and(|shared| shared.contains_key(key)) => {
+                self.template.remove(key);
+            }
+            None => {
+                self.template.insert(key.to_string(), value);
+            }
+        }
+    }
+
+    /// 记录局部帧 frame（None 为模板变量）中的变量是否已经转义过
+    fn mark(&mut self, frame: Option<usize>, key: &str, safe: bool) {
+        let marks = match frame {
+            Some(index) => &mut self.frames[index].safe,
+            None => &mut self.safe,
+        };
+        if safe {
+            marks.insert(key.to_string());
+        } else {
+            marks.remove(key);
+        }
+    }
+
+    /// 变量的值是否已经转义过，按查找变量时的顺序取最内层的同名变量
+    pub fn is_safe(&self, key: &str) -> bool {
+        match self.frames.iter().rev().find(|frame| frame.variables.contains_key(key)) {
+            Some(frame) => frame.safe.contains(key),
+            None => self.safe.contains(key),
+        }
+    }
+
+    /// 可以修改的变量：局部帧或模板变量，工具变量是只读的；只读的调用者变量先复制到模板变量中再修改
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        if let Some(index) = self.frames.iter().rposition(|frame| frame.variables.contains_key(key)) {
+            return self.frames[index].variables.get_mut(key);
+        }
+        if !self.template.contains_key(key) {
+            let value = self.shared?.get(key)?.clone();
+            self.template.insert(key.to_string(), value);
+        }
+        self.template.get_mut(key)
+    }
+
+    /// 所有以 prefix 开头的变量，内层的变量覆盖外层的同名变量
+    pub fn prefixed(&self, prefix: &str) -> Vec<(String, Value)> {
+        let layers = std::iter::once(self.globals)
+            .chain(self.shared)
+            .chain(std::iter::once(&*self.template))
+            .chain(self.frames.iter().map(|frame| &frame.variables));
+        let mut entries: HashMap<&str, &Value> = HashMap::new();
+        for layer in layers {
+            entries.extend(layer.iter().filter(|(key, _)| key.starts_with(prefix)).map(|(key, value)| (key.as_str(), value)));
+        }
+        entries.into_iter().map(|(key, value)| (key.to_string(), value.clone())).collect()
+    }
+}
+
+impl Variables for Context<'_> {
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.variables.get(key))
+            .or_else(|| self.template.get(key))
+            .or_else(|| self.shared?.get(key))
+            .or_else(|| self.globals.get(key))
+    }
+}
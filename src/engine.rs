@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use serde::Serialize;
+use serde_json::Value;
+use crate::cache::{CachePolicy, CacheStats, TemplateCache};
+use crate::config::{Escape, RenderConfig, SetScope, SpaceGobbling};
+use crate::context::Context;
+use crate::error::{VelocityError, INLINE_TEMPLATE_NAME};
+use crate::event::{IncludeHandler, InvalidReferenceHandler, MethodExceptionHandler, ReferenceInsertionHandler};
+use crate::loader::{LoaderChain, ResourceLoader};
+use crate::token::token_parse::{self, RenderState, Tokenizer};
+
+/// 模板引擎：保存配置、模板缓存和工具变量。克隆后共享同一份数据，
+/// 不同配置的引擎之间互不影响
+#[derive(Debug, Clone, Default)]
+pub struct VelocityEngine {
+    inner: Arc<EngineInner>,
+}
+
+#[derive(Debug, Default)]
+struct EngineInner {
+    config: RenderConfig,
+    cache: TemplateCache,
+    tools: HashMap<String, Value>,
+}
+
+impl VelocityEngine {
+    pub fn builder() -> VelocityEngineBuilder {
+        VelocityEngineBuilder::default()
+    }
+
+    pub fn config(&self) -> &RenderConfig {
+        &self.inner.config
+    }
+
+    /// 编译模板字符串，得到的 Template 可以反复渲染
+    pub fn compile(&self, source: &str) -> Result<Template, VelocityError> {
+        self.compile_named(INLINE_TEMPLATE_NAME, source)
+    }
+
+    /// 通过加载器读取并编译模板，模板修改时间变化后重新加载
+    pub fn get_template(&self, name: &str) -> Result<Template, VelocityError> {
+        let loader = &self.inner.config.loader;
+        let (source, tokens) = self.inner.cache.get_or_load(name, loader.last_modified(name), || {
+            let source = loader.load(name)?;
+            let tokens = token_parse::get_named_tokens(name, &source, &self.inner.config)?;
+            Ok((Arc::from(source), Arc::new(tokens)))
+        })?;
+        Ok(Template::new(self.clone(), name, source, tokens))
+    }
+
+    pub(crate) fn compile_named(&self, name: &str, source: &str) -> Result<Template, VelocityError> {
+        let tokens = self.parse(name, source)?;
+        Ok(Template::new(self.clone(), name, Arc::from(source), tokens))
+    }
+
+    /// 渲染模板字符串，`#set` 等对上下文的修改会保留在 context 中
+    pub fn render(&self, template: &str, context: &mut HashMap<String, Value>) -> Result<String, VelocityError> {
+        self.render_named(INLINE_TEMPLATE_NAME, template, context)
+    }
+
+    /// 渲染模板字符串并写入 writer
+    pub fn render_to<W: io::Write>(&self, template: &str, context: &mut HashMap<String, Value>, writer: &mut W) -> Result<(), VelocityError> {
+        self.compile(template)?.render_to_mut(context, writer)
+    }
+
+    /// 通过加载器读取并渲染模板
+    pub fn render_template(&self, name: &str, context: &mut HashMap<String, Value>) -> Result<String, VelocityError> {
+        self.get_template(name)?.render_mut(context)
+    }
+
+    /// 模板缓存的命中次数、未命中次数和当前缓存数量
+    pub fn cache_stats(&self) -> CacheStats {
+        self.inner.cache.stats()
+    }
+
+    pub fn clear_cache(&self) {
+        self.inner.cache.clear()
+    }
+
+    /// 以对象序列化后的字段作为变量渲染模板
+    pub fn render_object<T: Serialize>(&self, template: &str, obj: &T) -> Result<String, VelocityError> {
+        let mut context = crate::object_to_hashmap(obj)?;
+        self.render(template, &mut context)
+    }
+
+    /// 解析模板，按缓存策略复用已解析的语法树
+    pub(crate) fn parse(&self, name: &str, template: &str) -> Result<Arc<Vec<Tokenizer>>, VelocityError> {
+        self.inner
+            .cache
+            .get_or_parse(template, || token_parse::get_named_tokens(name, template, &self.inner.config))
+    }
+
+    pub(crate) fn render_named(&self, name: &str, template: &str, context: &mut HashMap<String, Value>) -> Result<String, VelocityError> {
+        self.compile_named(name, template)?.render_mut(context)
+    }
+
+    /// 渲染已解析的模板，结果直接写入 out
+    fn render_tokens(&self, template: &TemplateInner, mut context: Context, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+        let mut state = RenderState::new(self, &template.name, template.source.clone());
+        state.escape = template.escape;
+        state.define_macros(&template.tokens);
+        token_parse::parse_tokens(&template.tokens, &mut context, &mut state, out)
+    }
+}
+
+/// 编译后的模板，不可变、可以在多个线程中共享，克隆只复制一个 Arc
+#[derive(Debug, Clone)]
+pub struct Template {
+    inner: Arc<TemplateInner>,
+}
+
+#[derive(Debug)]
+struct TemplateInner {
+    engine: VelocityEngine,
+    name: String,
+    source: Arc<str>,
+    tokens: Arc<Vec<Tokenizer>>,
+    escape: Escape,
+}
+
+impl Template {
+    fn new(engine: VelocityEngine, name: &str, source: Arc<str>, tokens: Arc<Vec<Tokenizer>>) -> Self {
+        Template {
+            inner: Arc::new(TemplateInner {
+                escape: engine.config().escape,
+                engine,
+                name: name.to_string(),
+                source,
+                tokens,
+            }),
+        }
+    }
+
+    /// 使用另一种自动转义方式的模板，与原模板共享语法树，`#parse` 引入的模板使用同样的转义方式
+    pub fn with_escape(&self, escape: Escape) -> Template {
+        let inner = &self.inner;
+        Template {
+            inner: Arc::new(TemplateInner {
+                engine: inner.engine.clone(),
+                name: inner.name.clone(),
+                source: inner.source.clone(),
+                tokens: inner.tokens.clone(),
+                escape,
+            }),
+        }
+    }
+
+    pub fn escape(&self) -> Escape {
+        self.inner.escape
+    }
+
+    pub(crate) fn source_arc(&self) -> &Arc<str> {
+        &self.inner.source
+    }
+
+    pub(crate) fn tokens(&self) -> &[Tokenizer] {
+        &self.inner.tokens
+    }
+
+    /// 模板名称，内联模板为 `<inline>`
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.inner.source
+    }
+
+    /// 渲染模板，模板中的 `#set` 不会修改传入的 context，也不会复制 context
+    pub fn render(&self, context: &HashMap<String, Value>) -> Result<String, VelocityError> {
+        let mut output = String::new();
+        self.render_fmt(context, &mut output)?;
+        Ok(output)
+    }
+
+    /// 与 render 相同，但 `#set` 等对上下文的修改会保留在 context 中
+    pub fn render_mut(&self, context: &mut HashMap<String, Value>) -> Result<String, VelocityError> {
+        let mut output = String::new();
+        self.render_fmt_mut(context, &mut output)?;
+        Ok(output)
+    }
+
+    /// 边渲染边写入 writer，不在内存中拼接完整结果
+    pub fn render_to<W: io::Write>(&self, context: &HashMap<String, Value>, writer: &mut W) -> Result<(), VelocityError> {
+        write_io(writer, |out| self.render_fmt(context, out))
+    }
+
+    pub fn render_to_mut<W: io::Write>(&self, context: &mut HashMap<String, Value>, writer: &mut W) -> Result<(), VelocityError> {
+        write_io(writer, |out| self.render_fmt_mut(context, out))
+    }
+
+    /// 渲染到 `fmt::Write`，例如 String 或 Formatter
+    pub fn render_fmt<W: fmt::Write>(&self, context: &HashMap<String, Value>, writer: &mut W) -> Result<(), VelocityError> {
+        // 工具变量在最外层，不覆盖同名的用户变量；context 只读，`#set` 写入的变量在渲染结束后丢弃
+        let engine = &self.inner.engine;
+        let mut variables = HashMap::new();
+        let context = Context::layered(&engine.inner.tools, context, &mut variables, engine.config().set_scope);
+        engine.render_tokens(&self.inner, context, writer)
+    }
+
+    pub fn render_fmt_mut<W: fmt::Write>(&self, context: &mut HashMap<String, Value>, writer: &mut W) -> Result<(), VelocityError> {
+        let engine = &self.inner.engine;
+        let context = Context::new(&engine.inner.tools, context, engine.config().set_scope);
+        engine.render_tokens(&self.inner, context, writer)
+    }
+
+    /// 以对象序列化后的字段作为变量渲染模板
+    pub fn render_object<T: Serialize>(&self, obj: &T) -> Result<String, VelocityError> {
+        self.render_mut(&mut crate::object_to_hashmap(obj)?)
+    }
+}
+
+/// 渲染到 `io::Write`，返回 writer 实际的 io 错误
+fn write_io<W: io::Write>(writer: &mut W, render: impl FnOnce(&mut IoWriter<W>) -> Result<(), VelocityError>) -> Result<(), VelocityError> {
+    let mut out = IoWriter { inner: writer, error: None };
+    let result = render(&mut out);
+    match out.error {
+        Some(source) => Err(VelocityError::Write { source }),
+        None => result,
+    }
+}
+
+/// 把 `io::Write` 适配为 `fmt::Write`，保存第一个写入错误
+struct IoWriter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error.get_or_insert(err);
+            fmt::Error
+        })
+    }
+}
+
+/// VelocityEngine 的构建器
+#[derive(Debug, Default)]
+pub struct VelocityEngineBuilder {
+    config: RenderConfig,
+    loaders: Vec<Arc<dyn ResourceLoader>>,
+    cache_policy: CachePolicy,
+    tools: HashMap<String, Value>,
+}
+
+impl VelocityEngineBuilder {
+    /// 添加模板加载器，添加多个时按添加顺序查找；不添加时从当前目录读取文件
+    pub fn loader(mut self, loader: impl ResourceLoader + 'static) -> Self {
+        self.loaders.push(Arc::new(loader));
+        self
+    }
+
+    pub fn space_gobbling(mut self, space_gobbling: SpaceGobbling) -> Self {
+        self.config.space_gobbling = space_gobbling;
+        self
+    }
+
+    /// `#set` 写入变量的位置，默认 SetScope::Global
+    pub fn set_scope(mut self, set_scope: SetScope) -> Self {
+        self.config.set_scope = set_scope;
+        self
+    }
+
+    /// 是否写入 `$item.index` 这类以元素名开头的循环变量，默认关闭，只能使用 `$foreach.index` 等；
+    /// 开启后元素自己的 `index`、`count` 等字段会被遮住
+    pub fn element_loop_variables(mut self, enabled: bool) -> Self {
+        self.config.element_loop_variables = enabled;
+        self
+    }
+
+    /// 引用输出时的自动转义方式，默认不转义
+    pub fn escape(mut self, escape: Escape) -> Self {
+        self.config.escape = escape;
+        self
+    }
+
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// 宏调用的最大嵌套深度，默认 20
+    pub fn max_macro_depth(mut self, max_macro_depth: usize) -> Self {
+        self.config.max_macro_depth = max_macro_depth;
+        self
+    }
+
+    /// `#parse` 的最大嵌套深度，默认 10
+    pub fn max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.config.max_include_depth = max_include_depth;
+        self
+    }
+
+    /// `[1..$n]` 计算为数组时最多包含的元素个数，默认 100000，超出时返回 `VelocityError::Expression`
+    pub fn max_range_length(mut self, max_range_length: usize) -> Self {
+        self.config.max_range_length = max_range_length;
+        self
+    }
+
+    /// 严格模式，默认关闭。开启后引用未定义、属性路径中间为 null、`#if` 的条件不是布尔值
+    /// 或表达式有误时返回带位置的错误，`$!name` 形式的静默引用除外
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.strict = strict;
+        self
+    }
+
+    /// 输出引用的值之前调用，注册多个时按注册顺序依次转换
+    pub fn reference_insertion_handler(mut self, handler: impl ReferenceInsertionHandler + 'static) -> Self {
+        self.config.events.reference_insertion.push(Arc::new(handler));
+        self
+    }
+
+    /// 引用未定义或值为 null 时调用，包括 `#if`、`#set` 等指令中的引用
+    pub fn invalid_reference_handler(mut self, handler: impl InvalidReferenceHandler + 'static) -> Self {
+        self.config.events.invalid_reference.push(Arc::new(handler));
+        self
+    }
+
+    /// `#parse`、`#include` 引入模板之前调用，可以改写或拒绝模板名称
+    pub fn include_handler(mut self, handler: impl IncludeHandler + 'static) -> Self {
+        self.config.events.include.push(Arc::new(handler));
+        self
+    }
+
+    /// 内置方法调用出错时调用，可以给出方法的结果
+    pub fn method_exception_handler(mut self, handler: impl MethodExceptionHandler + 'static) -> Self {
+        self.config.events.method_exception.push(Arc::new(handler));
+        self
+    }
+
+    /// 注册工具变量，所有模板都可以使用，例如 `$app.name`
+    pub fn tool(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.tools.insert(name.into(), value);
+        self
+    }
+
+    pub fn build(self) -> VelocityEngine {
+        let mut config = self.config;
+        let mut loaders = self.loaders;
+        if loaders.len() == 1 {
+            config.loader = loaders.remove(0);
+        } else if !loaders.is_empty() {
+            config.loader = Arc::new(LoaderChain { loaders });
+        }
+
+        VelocityEngine {
+            inner: Arc::new(EngineInner {
+                config,
+                cache: TemplateCache::new(self.cache_policy),
+                tools: self.tools,
+            }),
+        }
+    }
+}
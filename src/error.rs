@@ -0,0 +1,168 @@
+use std::fmt;
+
+/// 内联模板（非文件）使用的默认名称
+pub const INLINE_TEMPLATE_NAME: &str = "<inline>";
+
+/// 错误在模板中的位置：模板名、字节偏移、行号、列号，以及带 `^` 标记的源码片段
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub template: String,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl Location {
+    /// 根据字节偏移计算行号、列号（均从 1 开始，列按字符计）并截取出错行
+    pub fn new(template: &str, source: &str, offset: usize) -> Self {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[offset..].find('\n').map(|i| offset + i).unwrap_or(source.len());
+        let line = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..offset].chars().count() + 1;
+
+        let line_text = source[line_start..line_end].trim_end_matches('\r');
+        let snippet = format!("{}\n{}^", line_text, " ".repeat(column - 1));
+
+        Location {
+            template: template.to_string(),
+            offset,
+            line,
+            column,
+            snippet,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.template, self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub enum VelocityError {
+    /// 模板语法错误，例如缺少括号
+    Syntax {
+        message: String,
+        location: Box<Location>,
+    },
+    /// `#end` 没有对应的开始标签，或者块没有 `#end` 结尾
+    UnmatchedBlock {
+        directive: String,
+        location: Box<Location>,
+    },
+    /// `#set` 语法错误
+    InvalidSet {
+        message: String,
+        location: Box<Location>,
+    },
+    /// `#foreach` 语法错误
+    InvalidForeach {
+        message: String,
+        location: Box<Location>,
+    },
+    /// `#macro` 定义语法错误
+    InvalidMacro {
+        message: String,
+        location: Box<Location>,
+    },
+    /// 宏调用失败，例如参数过多、递归超过最大深度
+    MacroCall {
+        name: String,
+        message: String,
+        location: Box<Location>,
+    },
+    /// `#parse` 循环引用或嵌套过深，`#parse`、`#include` 找不到模板或读取失败
+    Include {
+        path: String,
+        message: String,
+        location: Box<Location>,
+    },
+    /// 加载器中找不到模板
+    NotFound {
+        name: String,
+    },
+    /// 读取模板文件失败
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// 写入渲染结果失败
+    Write {
+        source: std::io::Error,
+    },
+    /// 表达式无法计算
+    Expression {
+        message: String,
+        location: Option<Box<Location>>,
+    },
+    /// 引用了未定义的变量
+    UndefinedReference {
+        name: String,
+        location: Box<Location>,
+    },
+    /// 渲染上下文无效，例如对象序列化后不是 JSON 对象
+    Context {
+        message: String,
+    },
+}
+
+impl VelocityError {
+    /// 错误发生的位置，没有位置信息时返回 None
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            VelocityError::Syntax { location, .. }
+            | VelocityError::UnmatchedBlock { location, .. }
+            | VelocityError::InvalidSet { location, .. }
+            | VelocityError::InvalidForeach { location, .. }
+            | VelocityError::InvalidMacro { location, .. }
+            | VelocityError::MacroCall { location, .. }
+            | VelocityError::Include { location, .. }
+            | VelocityError::UndefinedReference { location, .. } => Some(location.as_ref()),
+            VelocityError::Expression { location, .. } => location.as_deref(),
+            VelocityError::NotFound { .. }
+            | VelocityError::Io { .. }
+            | VelocityError::Write { .. }
+            | VelocityError::Context { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for VelocityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VelocityError::Syntax { message, .. } => write!(f, "syntax error: {}", message)?,
+            VelocityError::UnmatchedBlock { directive, .. } => write!(f, "unmatched {}", directive)?,
+            VelocityError::InvalidSet { message, .. } => write!(f, "invalid #set: {}", message)?,
+            VelocityError::InvalidForeach { message, .. } => write!(f, "invalid #foreach: {}", message)?,
+            VelocityError::InvalidMacro { message, .. } => write!(f, "invalid #macro: {}", message)?,
+            VelocityError::MacroCall { name, message, .. } => write!(f, "invalid call to #{}: {}", name, message)?,
+            VelocityError::Include { path, message, .. } => write!(f, "cannot load '{}': {}", path, message)?,
+            VelocityError::NotFound { name } => return write!(f, "template '{}' not found", name),
+            VelocityError::Io { path, source } => return write!(f, "failed to read '{}': {}", path, source),
+            VelocityError::Write { source } => return write!(f, "failed to write output: {}", source),
+            VelocityError::Expression { message, .. } => write!(f, "expression error: {}", message)?,
+            VelocityError::UndefinedReference { name, .. } => write!(f, "undefined reference ${}", name)?,
+            VelocityError::Context { message } => return write!(f, "invalid context: {}", message),
+        }
+        if let Some(location) = self.location() {
+            write!(f, " at {}\n{}", location, location.snippet)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VelocityError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VelocityError::Io { source, .. } | VelocityError::Write { source } => Some(source),
+            _ => None,
+        }
+    }
+}
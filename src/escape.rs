@@ -0,0 +1,65 @@
+use std::borrow::Cow;
+use crate::config::Escape;
+
+/// 按转义方式处理引用输出的文本，不需要转义时不复制
+pub fn escape(mode: Escape, text: &str) -> Cow<'_, str> {
+    match mode {
+        Escape::None => Cow::Borrowed(text),
+        Escape::Html => replace(text, |c| match c {
+            '&' => Some("&amp;".into()),
+            '<' => Some("&lt;".into()),
+            '>' => Some("&gt;".into()),
+            '"' => Some("&quot;".into()),
+            '\'' => Some("&#39;".into()),
+            _ => None,
+        }),
+        Escape::Xml => replace(text, |c| match c {
+            '&' => Some("&amp;".into()),
+            '<' => Some("&lt;".into()),
+            '>' => Some("&gt;".into()),
+            '"' => Some("&quot;".into()),
+            '\'' => Some("&apos;".into()),
+            _ => None,
+        }),
+        Escape::Json => replace(text, |c| script_char(c, false)),
+        Escape::JavaScript => replace(text, |c| script_char(c, true)),
+        // SQL 字符串中的单引号写两次；MySQL 等把 `\` 当作转义符的数据库中，`\'` 会让后面的单引号结束字符串，所以 `\` 也写两次
+        Escape::Sql => replace(text, |c| match c {
+            '\'' => Some("''".into()),
+            '\\' => Some("\\\\".into()),
+            _ => None,
+        }),
+    }
+}
+
+/// JSON 和 JavaScript 字符串内容（不含两边的引号）中需要转义的字符；
+/// JavaScript 还转义单引号和 `/`，避免 `</script>` 提前结束脚本
+fn script_char(c: char, javascript: bool) -> Option<Cow<'static, str>> {
+    match c {
+        '"' => Some("\\\"".into()),
+        '\\' => Some("\\\\".into()),
+        '\n' => Some("\\n".into()),
+        '\r' => Some("\\r".into()),
+        '\t' => Some("\\t".into()),
+        '\'' if javascript => Some("\\'".into()),
+        '/' if javascript => Some("\\/".into()),
+        c if c.is_control() => Some(format!("\\u{:04x}", c as u32).into()),
+        _ => None,
+    }
+}
+
+fn replace(text: &str, replacement: impl Fn(char) -> Option<Cow<'static, str>>) -> Cow<'_, str> {
+    let first = match text.char_indices().find(|(_, c)| replacement(*c).is_some()) {
+        Some((index, _)) => index,
+        None => return Cow::Borrowed(text),
+    };
+    let mut escaped = String::with_capacity(text.len() + 16);
+    escaped.push_str(&text[..first]);
+    for c in text[first..].chars() {
+        match replacement(c) {
+            Some(replacement) => escaped.push_str(&replacement),
+            None => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
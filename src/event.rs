@@ -0,0 +1,72 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use serde_json::Value;
+use crate::error::Location;
+
+/// 输出引用的值之前调用，可以转换要输出的值，例如转义 HTML
+pub trait ReferenceInsertionHandler: Debug + Send + Sync {
+    /// reference 为引用的名称（不含 `$`），返回实际输出的值
+    fn reference_insert(&self, reference: &str, value: Value) -> Value;
+}
+
+/// 引用未定义或值为 null 时调用，包括输出的引用以及 `#if`、`#set` 等指令的表达式中的引用；
+/// `$!name` 形式的静默引用除外
+pub trait InvalidReferenceHandler: Debug + Send + Sync {
+    /// 返回 Some 时使用返回的值；返回 None 时交给下一个处理器，都返回 None 时按默认规则处理
+    fn invalid_reference(&self, reference: &str, location: &Location) -> Option<Value>;
+}
+
+/// `#parse`、`#include` 引入模板之前调用，可以改写或拒绝模板名称
+pub trait IncludeHandler: Debug + Send + Sync {
+    /// directive 为 `parse` 或 `include`，current 为当前模板名称；
+    /// 返回实际引入的模板名称，返回 None 时不引入、不输出任何内容
+    fn include(&self, path: &str, current: &str, directive: &str) -> Option<String>;
+}
+
+/// 内置方法调用出错时调用，例如参数不对或下标越界
+pub trait MethodExceptionHandler: Debug + Send + Sync {
+    /// 返回 Some 时作为方法调用的结果；返回 None 时交给下一个处理器，都返回 None 时按原来的错误处理
+    fn method_exception(&self, value: &Value, method: &str, message: &str) -> Option<Value>;
+}
+
+/// 引擎注册的事件处理器，同一种处理器按注册顺序调用
+#[derive(Debug, Clone, Default)]
+pub struct EventHandlers {
+    pub reference_insertion: Vec<Arc<dyn ReferenceInsertionHandler>>,
+    pub invalid_reference: Vec<Arc<dyn InvalidReferenceHandler>>,
+    pub include: Vec<Arc<dyn IncludeHandler>>,
+    pub method_exception: Vec<Arc<dyn MethodExceptionHandler>>,
+}
+
+impl EventHandlers {
+    /// 依次交给每个处理器转换
+    pub fn reference_insert(&self, reference: &str, value: Value) -> Value {
+        self.reference_insertion
+            .iter()
+            .fold(value, |value, handler| handler.reference_insert(reference, value))
+    }
+
+    /// 没有注册处理器时不计算位置
+    pub fn invalid_reference(&self, reference: &str, location: impl FnOnce() -> Box<Location>) -> Option<Value> {
+        if self.invalid_reference.is_empty() {
+            return None;
+        }
+        let location = location();
+        self.invalid_reference
+            .iter()
+            .find_map(|handler| handler.invalid_reference(reference, &location))
+    }
+
+    /// 依次改写模板名称，任何一个处理器拒绝时返回 None
+    pub fn include(&self, path: &str, current: &str, directive: &str) -> Option<String> {
+        self.include
+            .iter()
+            .try_fold(path.to_string(), |path, handler| handler.include(&path, current, directive))
+    }
+
+    pub fn method_exception(&self, value: &Value, method: &str, message: &str) -> Option<Value> {
+        self.method_exception
+            .iter()
+            .find_map(|handler| handler.method_exception(value, method, message))
+    }
+}
@@ -0,0 +1,125 @@
+use serde_json::Value;
+
+/// 表达式语法树，`#if` 的条件、`#set` 的值、宏参数等都会解析成 Expr
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// 数字、布尔、null 以及单引号字符串
+    Literal(Value),
+    /// 双引号字符串，其中的引用在计算时替换
+    Interpolated(Vec<StringPart>),
+    /// `$name`、`${a.b}`、`$!name`、`$list[0]`
+    Reference {
+        reference: Reference,
+        quiet: bool,
+        /// 去掉 `$`、`!` 和 `{}` 之后的原文，交给 InvalidReferenceHandler
+        name: String,
+    },
+    /// `[a, b, c]`
+    List(Vec<Expr>),
+    /// `[1..$n]`，包含两端，start 大于 end 时递减
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+    /// `{"key": value}`
+    Map(Vec<(Expr, Expr)>),
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+/// 引用：变量名以及后面的属性、下标和方法调用，例如 `$user.address.city`、`$list[0]`、`$map.get("key")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub root: String,
+    pub path: Vec<Segment>,
+}
+
+impl Reference {
+    /// 路径只有属性时返回 `a.b.c` 形式的名称
+    pub fn dotted_name(&self) -> Option<String> {
+        let mut name = self.root.clone();
+        for segment in &self.path {
+            match segment {
+                Segment::Property(property) => {
+                    name.push('.');
+                    name.push_str(property);
+                }
+                _ => return None,
+            }
+        }
+        Some(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// `.name`
+    Property(String),
+    /// `[expr]`
+    Index(Expr),
+    /// `.name(args)`
+    Method {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+/// 双引号字符串的组成部分
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    /// 引用以及它在字符串中的原文，引用未定义时原样输出
+    Reference(Expr, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `!`
+    Not,
+    /// `-`
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+        }
+    }
+}
@@ -1,88 +1,468 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use serde_json::{Map, Number, Value};
+use crate::expression::ast::{BinaryOp, Expr, Reference, Segment, StringPart, UnaryOp};
+use crate::context::Variables;
+use crate::error::Location;
+use crate::event::EventHandlers;
+use crate::expression::{methods, ExpressionError};
 
-use evalexpr::{ Value};
+/// 在上下文中计算表达式的值，未定义的引用为 null
+pub fn evaluate(expr: &Expr, context: &dyn Variables) -> Result<Value, ExpressionError> {
+    Evaluator::new(context, false).evaluate(expr)
+}
+
+/// 按路径查找引用的值，变量未定义或路径中途找不到时返回 None
+pub fn resolve<'a>(reference: &Reference, context: &'a dyn Variables) -> Result<Option<Cow<'a, Value>>, ExpressionError> {
+    Evaluator::new(context, false).resolve(reference)
+}
+
+/// 计算表达式用到的上下文。严格模式下引用未定义、属性路径中间为 null 或方法不存在时返回错误，
+/// `$!name` 形式的静默引用除外
+pub struct Evaluator<'a> {
+    context: &'a dyn Variables,
+    strict: bool,
+    events: Option<&'a EventHandlers>,
+    max_range_length: usize,
+    /// 模板名称、模板内容以及表达式在模板中的位置，引用无效时交给 InvalidReferenceHandler
+    location: Option<(&'a str, &'a str, usize)>,
+}
+
+/// `[1..$n]` 计算为数组时默认最多包含的元素个数
+pub const DEFAULT_MAX_RANGE_LENGTH: usize = 100_000;
 
-pub fn eval(input: &str) -> bool {
-    // 计算表达式
-    let result = evalexpr::eval(input);
+impl<'a> Evaluator<'a> {
+    pub fn new(context: &'a dyn Variables, strict: bool) -> Self {
+        Evaluator { context, strict, events: None, max_range_length: DEFAULT_MAX_RANGE_LENGTH, location: None }
+    }
+
+    /// 渲染指令时计算表达式：引用未定义或值为 null 时交给 InvalidReferenceHandler，位置为表达式的开头
+    pub fn at(mut self, template: &'a str, source: &'a str, offset: usize) -> Self {
+        self.location = Some((template, source, offset));
+        self
+    }
 
-    // 处理计算错误
-    if result.is_err() {
-        // println!("Error evaluating expression: {}", result.err().unwrap());
-        return false; // 返回一个默认值，表示错误时的结果
+    /// `[1..$n]` 计算为数组时最多包含的元素个数，超出时返回错误
+    pub fn with_max_range_length(mut self, max_range_length: usize) -> Self {
+        self.max_range_length = max_range_length;
+        self
     }
 
-    // 获取计算结果
-    let value = result.unwrap();
+    /// 方法调用出错时交给事件处理器
+    pub fn with_events(mut self, events: &'a EventHandlers) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn evaluate(&self, expr: &Expr) -> Result<Value, ExpressionError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Interpolated(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(literal) => text.push_str(literal),
+                        StringPart::Reference(reference, source) => match self.evaluate(reference)? {
+                            Value::Null if is_quiet(reference) => {}
+                            Value::Null => text.push_str(source),
+                            value => text.push_str(&value_to_string(&value)),
+                        },
+                    }
+                }
+                Ok(Value::String(text))
+            }
+            Expr::Reference { reference, quiet, name } => {
+                let resolved = if *quiet { self.lenient().resolve(reference) } else { self.resolve(reference) };
+                match resolved {
+                    Ok(Some(value)) if !value.is_null() => Ok(value.into_owned()),
+                    resolved => match self.invalid_reference(name, *quiet) {
+                        Some(value) => Ok(value),
+                        None => resolved.map(|value| value.map(Cow::into_owned).unwrap_or(Value::Null)),
+                    },
+                }
+            }
+            Expr::List(items) => items
+                .iter()
+                .map(|item| self.evaluate(item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            Expr::Range { start, end } => {
+                let (start, end) = self.range_bounds(start, end)?;
+                let length = range_length(start, end);
+                if length > self.max_range_length as u64 {
+                    return Err(ExpressionError::limit(format!(
+                        "range [{}..{}] has {} items, more than the maximum of {}",
+                        start, end, length, self.max_range_length
+                    )));
+                }
+                Ok(Value::Array(range_items(start, end).collect()))
+            }
+            Expr::Map(entries) => {
+                let mut map = Map::new();
+                for (key, value) in entries {
+                    let key = value_to_string(&self.evaluate(key)?);
+                    map.insert(key, self.evaluate(value)?);
+                }
+                Ok(Value::Object(map))
+            }
+            Expr::Unary { op: UnaryOp::Not, operand } => Ok(Value::Bool(!is_truthy(&self.evaluate(operand)?))),
+            Expr::Unary { op: UnaryOp::Neg, operand } => negate(&self.evaluate(operand)?),
+            // 逻辑运算短路计算
+            Expr::Binary { op: BinaryOp::And, left, right } => {
+                let result = is_truthy(&self.evaluate(left)?) && is_truthy(&self.evaluate(right)?);
+                Ok(Value::Bool(result))
+            }
+            Expr::Binary { op: BinaryOp::Or, left, right } => {
+                let result = is_truthy(&self.evaluate(left)?) || is_truthy(&self.evaluate(right)?);
+                Ok(Value::Bool(result))
+            }
+            Expr::Binary { op, left, right } => binary(*op, &self.evaluate(left)?, &self.evaluate(right)?),
+        }
+    }
+
+    /// 按路径查找引用的值，变量未定义或路径中途找不到时返回 None（严格模式下返回错误）
+    pub fn resolve(&self, reference: &Reference) -> Result<Option<Cow<'a, Value>>, ExpressionError> {
+        let (root, used) = match lookup_root(reference, self.context) {
+            Some(found) => found,
+            None if self.strict => return Err(ExpressionError::undefined(reference.root.clone())),
+            None => return Ok(None),
+        };
+
+        // 严格模式的错误信息中使用的引用名称，例如 `user.address`
+        let mut name = String::new();
+        if self.strict {
+            name.push_str(&reference.root);
+            for segment in &reference.path[..used] {
+                if let Segment::Property(property) = segment {
+                    name.push('.');
+                    name.push_str(property);
+                }
+            }
+        }
 
-    // 将结果转换为布尔值，如果转换失败，则返回 false
-    value.as_boolean().unwrap_or_else(|_| {
-        // println!("The result is not a boolean value.");
-        false
-    })
+        let mut current = Cow::Borrowed(root);
+        for segment in &reference.path[used..] {
+            let is_null = current.is_null();
+            let next = match segment {
+                Segment::Property(property) => {
+                    if self.strict {
+                        name.push('.');
+                        name.push_str(property);
+                    }
+                    property_of(current, property)
+                }
+                Segment::Index(index) => {
+                    let index = self.evaluate(index)?;
+                    if self.strict {
+                        name.push_str(&format!("[{}]", index));
+                    }
+                    element(current, &index)
+                }
+                Segment::Method { name: method, args } => {
+                    let args = args.iter().map(|arg| self.evaluate(arg)).collect::<Result<Vec<_>, _>>()?;
+                    if self.strict {
+                        name.push_str(&format!(".{}()", method));
+                    }
+                    self.call_method(current, method, &args)?
+                }
+            };
+            match next {
+                Some(value) => current = value,
+                None if !self.strict => return Ok(None),
+                None if is_null => {
+                    return Err(ExpressionError::new(format!("null value in ${}", name), 0));
+                }
+                None => return Err(ExpressionError::undefined(name)),
+            }
+        }
+        Ok(Some(current))
+    }
+
+    /// 计算 `[start..end]` 的上下界，`#foreach` 按上下界逐个循环，不需要生成数组
+    pub fn range_bounds(&self, start: &Expr, end: &Expr) -> Result<(i64, i64), ExpressionError> {
+        Ok((range_bound(&self.evaluate(start)?)?, range_bound(&self.evaluate(end)?)?))
+    }
+
+    /// 与输出引用相同，`$!name` 形式的静默引用不交给处理器；不在渲染指令时没有位置，也不交给处理器
+    fn invalid_reference(&self, name: &str, quiet: bool) -> Option<Value> {
+        match (self.events, self.location) {
+            (Some(events), Some((template, source, offset))) if !quiet => {
+                events.invalid_reference(name, || Box::new(Location::new(template, source, offset)))
+            }
+            _ => None,
+        }
+    }
+
+    /// 静默引用使用的非严格模式
+    fn lenient(&self) -> Evaluator<'a> {
+        Evaluator { strict: false, ..*self }
+    }
+
+    /// 调用内置方法，没有这个方法时返回 None。`get` 与 `[index]` 相同，不复制元素
+    fn call_method(&self, value: Cow<'a, Value>, name: &str, args: &[Value]) -> Result<Option<Cow<'a, Value>>, ExpressionError> {
+        if let ("get", [index]) = (name, args) {
+            if value.is_array() || value.is_object() {
+                return Ok(element(value, index));
+            }
+        }
+        match methods::call(&value, name, args) {
+            Some(Ok(result)) => Ok(Some(Cow::Owned(result))),
+            Some(Err(message)) => match self.events.and_then(|events| events.method_exception(&value, name, &message)) {
+                Some(result) => Ok(Some(Cow::Owned(result))),
+                None => Err(ExpressionError::new(format!("{}.{}(): {}", type_name(&value), name, message), 0)),
+            },
+            None => Ok(None),
+        }
+    }
 }
 
-pub fn eval_value(input: &str) -> Result<Value, String> {
-    let result = evalexpr::eval(input);
-    // 处理计算错误
-    if result.is_err() {
-        return Err(format!("Error evaluating expression: {}", result.err().unwrap())); // 返回一个默认值，表示错误时的结果
+/// 查找变量，返回值和已经使用的属性数量。foreach 写入的 `item.index` 这类带 `.` 的键优先
+fn lookup_root<'a>(reference: &Reference, context: &'a dyn Variables) -> Option<(&'a Value, usize)> {
+    let mut found = context.get(&reference.root).map(|value| (value, 0));
+    let mut key = String::new();
+    for (index, segment) in reference.path.iter().enumerate() {
+        let Segment::Property(name) = segment else { break };
+        if key.is_empty() {
+            key.push_str(&reference.root);
+        }
+        key.push('.');
+        key.push_str(name);
+        if let Some(value) = context.get(&key) {
+            found = Some((value, index + 1));
+        }
     }
-    // 获取计算结果
-    let value = result.unwrap();
-    Ok(value.clone())
+    found
 }
 
+/// 取 value 的子节点，借用的值继续借用，计算出来的值则复制子节点
+fn child<'a, F>(value: Cow<'a, Value>, get: F) -> Option<Cow<'a, Value>>
+where
+    F: for<'b> Fn(&'b Value) -> Option<&'b Value>,
+{
+    match value {
+        Cow::Borrowed(value) => get(value).map(Cow::Borrowed),
+        Cow::Owned(value) => get(&value).cloned().map(Cow::Owned),
+    }
+}
 
+/// `.name`：Map 的键；数组和没有 size 键的 Map 还支持 `.size`
+fn property_of<'a>(value: Cow<'a, Value>, name: &str) -> Option<Cow<'a, Value>> {
+    if name == "size" {
+        let size = match value.as_ref() {
+            Value::Array(list) => Some(list.len()),
+            Value::Object(map) if !map.contains_key(name) => Some(map.len()),
+            _ => None,
+        };
+        if let Some(size) = size {
+            return Some(Cow::Owned(Value::from(size)));
+        }
+    }
+    child(value, |value| value.as_object()?.get(name))
+}
+
+/// `[index]`：数组按整数下标，Map 按字符串键
+fn element<'a>(value: Cow<'a, Value>, index: &Value) -> Option<Cow<'a, Value>> {
+    match index {
+        Value::Number(n) => {
+            let index = usize::try_from(n.as_u64()?).ok()?;
+            child(value, |value| value.as_array()?.get(index))
+        }
+        Value::String(key) => child(value, |value| value.as_object()?.get(key)),
+        _ => None,
+    }
+}
+
+/// 范围的两端必须是整数，也可以是整数形式的字符串
+fn range_bound(value: &Value) -> Result<i64, ExpressionError> {
+    let bound = match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    bound.ok_or_else(|| ExpressionError::new(format!("range bounds must be integers, found {}", type_name(value)), 0))
+}
+
+/// `[start..end]` 包含的元素个数，两端都包含
+pub fn range_length(start: i64, end: i64) -> u64 {
+    start.abs_diff(end).saturating_add(1)
+}
+
+/// 从 start 到 end 逐个生成整数，start 大于 end 时递减
+pub fn range_items(start: i64, end: i64) -> impl Iterator<Item = Value> {
+    let step: i128 = if start <= end { 1 } else { -1 };
+    (0..range_length(start, end)).map(move |i| Value::from((start as i128 + i as i128 * step) as i64))
+}
 
+fn is_quiet(expr: &Expr) -> bool {
+    matches!(expr, Expr::Reference { quiet: true, .. })
+}
 
-pub fn is_valid_expression(input: &str) -> bool {
-    // 尝试评估表达式
-    let result = evalexpr::eval(input);
+/// 条件判断的规则：null、false、0、空字符串、空数组、空 Map 为假，其余为真
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(list) => !list.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
 
-    // 如果评估成功，返回 true；如果失败，返回 false
-    result.is_ok()
+/// 值输出到模板中的文本，字符串不带引号，数组和 Map 输出为 JSON
+pub fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => format!("{}", value),
+    }
 }
 
-pub fn eval_expression(input: &str) -> Result<bool, String> {
-    // 判断表达式是否有效
-    if !is_valid_expression(input) {
-        return Err("Invalid expression".to_string());
+/// 错误信息中使用的类型名称
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "list",
+        Value::Object(_) => "map",
     }
+}
 
-    // 评估表达式
-    let result = evalexpr::eval(input);
+/// `==` 的规则：数字按数值比较（1 == 1.0），null 只等于 null，
+/// 一边是字符串时按输出文本比较（17 == "17"），其余类型按值比较
+pub fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b) == Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => left.is_null() && right.is_null(),
+        (Value::String(_), _) | (_, Value::String(_)) => value_to_string(left) == value_to_string(right),
+        _ => left == right,
+    }
+}
 
-    // 处理计算结果
-    match result {
-        Ok(value) => {
-            // 尝试将结果转换为布尔值
-            match value.as_boolean() {
-                Ok(b) => Ok(b),
-                Err(e) => Err("Result is not a boolean".to_string()),
-            }
+/// `<`、`>` 等只能比较两个数字或两个字符串
+pub fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn compare_numbers(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+fn binary(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, ExpressionError> {
+    let unsupported = || {
+        ExpressionError::new(
+            format!("cannot apply '{}' to {} and {}", op.symbol(), type_name(left), type_name(right)),
+            0,
+        )
+    };
+
+    match op {
+        BinaryOp::Eq => Ok(Value::Bool(equals(left, right))),
+        BinaryOp::Ne => Ok(Value::Bool(!equals(left, right))),
+        BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => {
+            let ordering = compare(left, right).ok_or_else(unsupported)?;
+            let result = match op {
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::Gt => ordering.is_gt(),
+                BinaryOp::Le => ordering.is_le(),
+                _ => ordering.is_ge(),
+            };
+            Ok(Value::Bool(result))
+        }
+        // 有一边是字符串时 + 表示拼接
+        BinaryOp::Add if left.is_string() || right.is_string() => {
+            Ok(Value::String(value_to_string(left) + &value_to_string(right)))
+        }
+        _ => match (left, right) {
+            (Value::Number(a), Value::Number(b)) => arithmetic(op, a, b),
+            _ => Err(unsupported()),
+        },
+    }
+}
+
+/// 两个整数的运算结果仍是整数（除法取整），溢出或有小数参与时按浮点数计算
+fn arithmetic(op: BinaryOp, a: &Number, b: &Number) -> Result<Value, ExpressionError> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && b.as_f64() == Some(0.0) {
+        return Err(ExpressionError::new("division by zero", 0));
+    }
+
+    if let (Some(x), Some(y)) = (a.as_i64(), b.as_i64()) {
+        let result = match op {
+            BinaryOp::Add => x.checked_add(y),
+            BinaryOp::Sub => x.checked_sub(y),
+            BinaryOp::Mul => x.checked_mul(y),
+            BinaryOp::Div => x.checked_div(y),
+            _ => x.checked_rem(y),
+        };
+        if let Some(result) = result {
+            return Ok(Value::from(result));
         }
-        Err(e) => Err(format!("Error evaluating expression: {}", e)),
     }
+
+    let (x, y) = (a.as_f64().unwrap_or_default(), b.as_f64().unwrap_or_default());
+    let result = match op {
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::Mul => x * y,
+        BinaryOp::Div => x / y,
+        _ => x % y,
+    };
+    float(result)
+}
+
+fn negate(value: &Value) -> Result<Value, ExpressionError> {
+    match value {
+        Value::Number(n) => match n.as_i64().and_then(i64::checked_neg) {
+            Some(result) => Ok(Value::from(result)),
+            None => float(-n.as_f64().unwrap_or_default()),
+        },
+        _ => Err(ExpressionError::new(format!("cannot apply '-' to {}", type_name(value)), 0)),
+    }
+}
+
+fn float(value: f64) -> Result<Value, ExpressionError> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| ExpressionError::new("result is not a finite number", 0))
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::expression::expr_eval::eval_expression;
+    use std::collections::HashMap;
+    use serde_json::{json, Value};
+    use crate::expression::expr_eval::evaluate;
+    use crate::expression::expr_parser::parse;
+
+    fn eval(text: &str) -> Value {
+        let mut context: HashMap<String, Value> = HashMap::new();
+        context.insert("name".to_string(), json!("Tom && \"Jerry\""));
+        context.insert("age".to_string(), json!(17));
+        evaluate(&parse(text).unwrap(), &context).unwrap()
+    }
 
     #[test]
     fn main() {
-        let input = "true && false";
-        match eval_expression(input) {
-            Ok(result) => println!("Result: {}", result),
-            Err(e) => println!("Error: {}", e),
-        }
-
-        let invalid_input = "true &&";
-        match eval_expression(invalid_input) {
-            Ok(result) => println!("Result: {}", result),
-            Err(e) => println!("Error: {}", e),
-        }
+        assert_eq!(eval("1 + 2 * 3 - (4 - 2)"), json!(5));
+        assert_eq!(eval("7 / 2 + 0.5"), json!(3.5));
+        assert_eq!(eval("!true || 1 < 2 && 2 >= 3"), json!(false));
+        assert_eq!(eval("$name == 'Tom && \"Jerry\"'"), json!(true));
+        assert_eq!(eval("\"name: $name, $!missing$missing\""), json!("name: Tom && \"Jerry\", $missing"));
+        assert_eq!(eval("$age == \"17\" && $age == 17.0 && $missing == null"), json!(true));
+        assert_eq!(eval("[1, 'a', {\"k\": $age}]"), json!([1, "a", {"k": 17}]));
+        assert!(parse("1 +").is_err());
+        assert!(parse("$a = 1").is_err());
+        assert!(evaluate(&parse("1 / 0").unwrap(), &HashMap::new()).is_err());
     }
 
 }
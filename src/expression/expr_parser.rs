@@ -0,0 +1,420 @@
+use serde_json::Value;
+use crate::expression::ast::{BinaryOp, Expr, Reference, Segment, StringPart, UnaryOp};
+use crate::expression::ExpressionError;
+use crate::token::lexer;
+
+/// 括号、列表、下标以及一元运算最多嵌套的层数
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// 把表达式文本解析成语法树，优先级从低到高：
+/// `|| or`、`&& and`、`== != eq ne`、`< > <= >= lt gt le ge`、`+ -`、`* / %`、`! not -`
+pub fn parse(text: &str) -> Result<Expr, ExpressionError> {
+    let mut parser = ExprParser { input: text, pos: 0, depth: 0 };
+    let expr = parser.parse_or()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(expr)
+}
+
+struct ExprParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl ExprParser<'_> {
+    fn error(&self, message: impl Into<String>) -> ExpressionError {
+        ExpressionError::new(message, self.pos)
+    }
+
+    fn unexpected(&self) -> ExpressionError {
+        match self.input[self.pos..].chars().next() {
+            Some(c) => self.error(format!("unexpected '{}'", c)),
+            None => self.error("unexpected end of expression"),
+        }
+    }
+
+    /// 嵌套一层解析，超过 MAX_NESTING_DEPTH 层时报错
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, ExpressionError>) -> Result<T, ExpressionError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(self.error(format!("expression is nested deeper than {} levels", MAX_NESTING_DEPTH)));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// 跳过空白后，如果接下来是 symbol 就消费掉；`and` 等单词运算符后面不能紧跟标识符字符
+    fn eat(&mut self, symbol: &str) -> bool {
+        self.skip_whitespace();
+        if !self.input[self.pos..].starts_with(symbol) {
+            return false;
+        }
+        let end = self.pos + symbol.len();
+        if symbol.starts_with(|c: char| c.is_ascii_alphabetic()) && lexer::scan_identifier(self.input, self.pos) != end {
+            return false;
+        }
+        self.pos = end;
+        true
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}'", symbol)))
+        }
+    }
+
+    /// 依次尝试 operators 中的运算符，`<=` 等较长的运算符要排在 `<` 前面
+    fn eat_operator(&mut self, operators: &[(&str, BinaryOp)]) -> Option<BinaryOp> {
+        operators.iter().find(|(symbol, _)| self.eat(symbol)).map(|(_, op)| *op)
+    }
+
+    fn parse_binary(
+        &mut self,
+        operators: &[(&str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, ExpressionError>,
+    ) -> Result<Expr, ExpressionError> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.eat_operator(operators) {
+            let right = operand(self)?;
+            left = Expr::Binary { op, left: Box::new(left), right: Box::new(right) };
+        }
+        Ok(left)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ExpressionError> {
+        self.parse_binary(&[("||", BinaryOp::Or), ("or", BinaryOp::Or)], Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ExpressionError> {
+        self.parse_binary(&[("&&", BinaryOp::And), ("and", BinaryOp::And)], Self::parse_equality)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr, ExpressionError> {
+        let operators = [("==", BinaryOp::Eq), ("!=", BinaryOp::Ne), ("eq", BinaryOp::Eq), ("ne", BinaryOp::Ne)];
+        self.parse_binary(&operators, Self::parse_comparison)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ExpressionError> {
+        let operators = [
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+            ("le", BinaryOp::Le),
+            ("ge", BinaryOp::Ge),
+            ("lt", BinaryOp::Lt),
+            ("gt", BinaryOp::Gt),
+        ];
+        self.parse_binary(&operators, Self::parse_additive)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, ExpressionError> {
+        self.parse_binary(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::parse_multiplicative)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, ExpressionError> {
+        let operators = [("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Mod)];
+        self.parse_binary(&operators, Self::parse_unary)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExpressionError> {
+        let op = if self.eat("!") || self.eat("not") {
+            UnaryOp::Not
+        } else if self.eat("-") {
+            UnaryOp::Neg
+        } else {
+            return self.parse_primary();
+        };
+        let operand = self.nested(Self::parse_unary)?;
+        Ok(Expr::Unary { op, operand: Box::new(operand) })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ExpressionError> {
+        self.nested(Self::primary)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExpressionError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                self.parse_list()
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                self.parse_items("}", Self::parse_entry).map(Expr::Map)
+            }
+            Some(quote @ (b'"' | b'\'')) => self.parse_string(quote),
+            Some(b'$') => self.parse_reference().ok_or_else(|| self.error("invalid reference")),
+            Some(c) if c.is_ascii_digit() => self.parse_number(),
+            Some(_) => {
+                let end = lexer::scan_identifier(self.input, self.pos);
+                let literal = match &self.input[self.pos..end] {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    "null" => Value::Null,
+                    "" => return Err(self.unexpected()),
+                    word => return Err(self.error(format!("unexpected '{}'", word))),
+                };
+                self.pos = end;
+                Ok(Expr::Literal(literal))
+            }
+            None => Err(self.unexpected()),
+        }
+    }
+
+    /// `[a, b, c]` 或者范围 `[start..end]`
+    fn parse_list(&mut self) -> Result<Expr, ExpressionError> {
+        if self.eat("]") {
+            return Ok(Expr::List(Vec::new()));
+        }
+        let first = self.parse_or()?;
+        if self.eat("..") {
+            let end = self.parse_or()?;
+            self.expect("]")?;
+            return Ok(Expr::Range {
+                start: Box::new(first),
+                end: Box::new(end),
+            });
+        }
+        let mut items = vec![first];
+        loop {
+            if self.eat("]") {
+                return Ok(Expr::List(items));
+            }
+            self.expect(",")?;
+            items.push(self.parse_or()?);
+        }
+    }
+
+    /// 逗号分隔、以 close 结尾的列表项
+    fn parse_items<T>(
+        &mut self,
+        close: &str,
+        item: fn(&mut Self) -> Result<T, ExpressionError>,
+    ) -> Result<Vec<T>, ExpressionError> {
+        let mut items = Vec::new();
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn parse_entry(&mut self) -> Result<(Expr, Expr), ExpressionError> {
+        let key = self.parse_or()?;
+        self.expect(":")?;
+        Ok((key, self.parse_or()?))
+    }
+
+    fn parse_number(&mut self) -> Result<Expr, ExpressionError> {
+        let bytes = self.input.as_bytes();
+        let start = self.pos;
+        let mut end = start;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        // `1..5` 中的 `.` 不是小数点
+        let float = bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit);
+        if float {
+            end += 1;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+        }
+
+        let text = &self.input[start..end];
+        let value = match text.parse::<i64>() {
+            Ok(int) if !float => Value::from(int),
+            _ => text.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number)
+                .ok_or_else(|| self.error(format!("invalid number '{}'", text)))?,
+        };
+        self.pos = end;
+        Ok(Expr::Literal(value))
+    }
+
+    /// 字符串中连续两个引号表示一个引号；单引号字符串原样使用，双引号字符串中的引用会被替换
+    fn parse_string(&mut self, quote: u8) -> Result<Expr, ExpressionError> {
+        let quote = quote as char;
+        let start = self.pos;
+        let mut text = String::new();
+        let mut rest = &self.input[start + 1..];
+        loop {
+            let len = rest.find(quote).ok_or_else(|| ExpressionError::new("unterminated string literal", start))?;
+            text.push_str(&rest[..len]);
+            rest = &rest[len + 1..];
+            if !rest.starts_with(quote) {
+                break;
+            }
+            text.push(quote);
+            rest = &rest[1..];
+        }
+        self.pos = self.input.len() - rest.len();
+
+        if quote == '\'' {
+            return Ok(Expr::Literal(Value::String(text)));
+        }
+        Ok(interpolate(&text, self.depth))
+    }
+
+    /// 不是引用时返回 None 且不移动位置
+    fn parse_reference(&mut self) -> Option<Expr> {
+        let scanned = scan(self.input, self.pos, self.depth)?;
+        self.pos = scanned.end;
+        Some(Expr::Reference {
+            reference: scanned.reference,
+            quiet: scanned.quiet,
+            name: scanned.name,
+        })
+    }
+
+    /// 变量名后面的属性、下标和方法调用，解析失败的部分不属于引用
+    fn parse_path(&mut self) -> Vec<Segment> {
+        let mut path = Vec::new();
+        loop {
+            let start = self.pos;
+            match self.parse_segment() {
+                Some(segment) => path.push(segment),
+                None => {
+                    self.pos = start;
+                    return path;
+                }
+            }
+        }
+    }
+
+    fn parse_segment(&mut self) -> Option<Segment> {
+        match self.peek()? {
+            b'.' => {
+                let name_start = self.pos + 1;
+                let name_end = lexer::scan_identifier(self.input, name_start);
+                if name_end == name_start {
+                    return None;
+                }
+                let name = self.input[name_start..name_end].to_string();
+                self.pos = name_end;
+                if self.peek() != Some(b'(') {
+                    return Some(Segment::Property(name));
+                }
+                self.pos += 1;
+                let args = self.parse_items(")", Self::parse_or).ok()?;
+                Some(Segment::Method { name, args })
+            }
+            b'[' => {
+                self.pos += 1;
+                let index = self.parse_or().ok()?;
+                self.expect("]").ok()?;
+                Some(Segment::Index(index))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 从 `$` 开始扫描到的引用
+pub(crate) struct ScannedReference {
+    pub reference: Reference,
+    /// `$!name` 未定义时输出空字符串
+    pub quiet: bool,
+    /// 去掉 `$`、`!` 和 `{}` 之后的原文，例如 `user.name`、`list[0]`
+    pub name: String,
+    pub end: usize,
+}
+
+/// 扫描 `$name`、`$a.b`、`${a.b}`、`$!name`、`$!{name}`、`$list[0]`、`$map.get("key")` 形式的引用
+pub(crate) fn scan_reference(input: &str, start: usize) -> Option<ScannedReference> {
+    scan(input, start, 0)
+}
+
+/// 在嵌套了 depth 层的表达式中扫描引用，下标和方法参数中的表达式继续计入嵌套层数
+fn scan(input: &str, start: usize, depth: usize) -> Option<ScannedReference> {
+    let bytes = input.as_bytes();
+    let quiet = bytes.get(start + 1) == Some(&b'!');
+    let prefix = if quiet { 2 } else { 1 };
+    let braced = bytes.get(start + prefix) == Some(&b'{');
+    let name_start = if braced { start + prefix + 1 } else { start + prefix };
+    let root_end = lexer::scan_identifier(input, name_start);
+    if root_end == name_start {
+        return None;
+    }
+
+    let mut parser = ExprParser { input, pos: root_end, depth };
+    let path = parser.parse_path();
+    let name_end = parser.pos;
+    let mut end = name_end;
+    if braced {
+        if bytes.get(end) != Some(&b'}') {
+            return None;
+        }
+        end += 1;
+    }
+
+    Some(ScannedReference {
+        reference: Reference {
+            root: input[name_start..root_end].to_string(),
+            path,
+        },
+        quiet,
+        name: input[name_start..name_end].to_string(),
+        end,
+    })
+}
+
+/// 拆分双引号字符串中的引用，没有引用时就是普通字符串
+fn interpolate(text: &str, depth: usize) -> Expr {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut parser = ExprParser { input: text, pos: 0, depth };
+    while let Some(offset) = text[parser.pos..].find('$') {
+        let start = parser.pos + offset;
+        literal.push_str(&text[parser.pos..start]);
+        parser.pos = start;
+        match parser.parse_reference() {
+            Some(reference) => {
+                if !literal.is_empty() {
+                    parts.push(StringPart::Text(std::mem::take(&mut literal)));
+                }
+                parts.push(StringPart::Reference(reference, text[start..parser.pos].to_string()));
+            }
+            None => {
+                literal.push('$');
+                parser.pos += 1;
+            }
+        }
+    }
+    literal.push_str(&text[parser.pos..]);
+
+    if parts.is_empty() {
+        return Expr::Literal(Value::String(literal));
+    }
+    if !literal.is_empty() {
+        parts.push(StringPart::Text(literal));
+    }
+    Expr::Interpolated(parts)
+}
@@ -0,0 +1,273 @@
+use serde_json::Value;
+use crate::expression::expr_eval::{equals, type_name, value_to_string};
+
+/// 内置方法：接收调用者和参数，参数个数、类型不对或者越界时返回错误信息
+pub type Method = fn(&Value, &[Value]) -> Result<Value, String>;
+
+/// 字符串的方法，下标按字符计算
+const STRING_METHODS: [(&str, Method); 17] = [
+    ("length", string_length),
+    ("isEmpty", string_is_empty),
+    ("toUpperCase", to_upper_case),
+    ("toLowerCase", to_lower_case),
+    ("trim", trim),
+    ("substring", substring),
+    ("charAt", char_at),
+    ("replace", replace),
+    ("startsWith", starts_with),
+    ("endsWith", ends_with),
+    ("contains", string_contains),
+    ("indexOf", string_index_of),
+    ("lastIndexOf", last_index_of),
+    ("split", split),
+    ("concat", concat),
+    ("equalsIgnoreCase", equals_ignore_case),
+    ("matches", matches),
+];
+
+const LIST_METHODS: [(&str, Method); 4] = [
+    ("size", list_size),
+    ("isEmpty", list_is_empty),
+    ("contains", list_contains),
+    ("indexOf", list_index_of),
+];
+
+const MAP_METHODS: [(&str, Method); 6] = [
+    ("size", map_size),
+    ("isEmpty", map_is_empty),
+    ("keySet", key_set),
+    ("values", values),
+    ("containsKey", contains_key),
+    ("containsValue", contains_value),
+];
+
+/// 所有类型都有的方法
+const COMMON_METHODS: [(&str, Method); 3] = [
+    ("toString", to_string),
+    ("equals", equals_method),
+    ("safe", safe),
+];
+
+/// 调用 value 的内置方法，没有这个方法时返回 None。数组和 Map 的 `get` 在查找引用时处理
+pub fn call(value: &Value, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+    let methods: &[(&str, Method)] = match value {
+        Value::String(_) => &STRING_METHODS,
+        Value::Array(_) => &LIST_METHODS,
+        Value::Object(_) => &MAP_METHODS,
+        _ => &[],
+    };
+    methods
+        .iter()
+        .chain(COMMON_METHODS.iter())
+        .find(|(method, _)| *method == name)
+        .map(|(_, method)| method(value, args))
+}
+
+fn arity<const N: usize>(args: &[Value]) -> Result<&[Value; N], String> {
+    args.try_into()
+        .map_err(|_| format!("expected {} arguments, found {}", N, args.len()))
+}
+
+fn int_arg(arg: &Value) -> Result<i64, String> {
+    arg.as_i64()
+        .ok_or_else(|| format!("expected an integer, found {}", type_name(arg)))
+}
+
+/// 字符下标，允许等于长度（作为结束位置）
+fn char_index(arg: &Value, len: usize) -> Result<usize, String> {
+    let index = int_arg(arg)?;
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index <= len)
+        .ok_or_else(|| format!("index {} out of range for length {}", index, len))
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or_default()
+}
+
+/// 字节偏移转成字符下标
+fn char_position(s: &str, byte: Option<usize>) -> Value {
+    match byte {
+        Some(byte) => Value::from(s[..byte].chars().count()),
+        None => Value::from(-1),
+    }
+}
+
+fn string_length(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::from(text(value).chars().count()))
+}
+
+fn string_is_empty(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::Bool(text(value).is_empty()))
+}
+
+fn to_upper_case(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::String(text(value).to_uppercase()))
+}
+
+fn to_lower_case(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::String(text(value).to_lowercase()))
+}
+
+fn trim(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::String(text(value).trim().to_string()))
+}
+
+/// `substring(begin)`、`substring(begin, end)`
+fn substring(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let s = text(value);
+    let len = s.chars().count();
+    let (begin, end) = match args {
+        [begin] => (char_index(begin, len)?, len),
+        [begin, end] => (char_index(begin, len)?, char_index(end, len)?),
+        _ => return Err(format!("expected 1 or 2 arguments, found {}", args.len())),
+    };
+    if begin > end {
+        return Err(format!("begin {} is greater than end {}", begin, end));
+    }
+    Ok(Value::String(s.chars().skip(begin).take(end - begin).collect()))
+}
+
+fn char_at(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [index] = arity::<1>(args)?;
+    let s = text(value);
+    let len = s.chars().count();
+    let index = char_index(index, len).ok().filter(|index| *index < len)
+        .ok_or_else(|| format!("index {} out of range for length {}", value_to_string(index), len))?;
+    Ok(Value::String(s.chars().nth(index).map(String::from).unwrap_or_default()))
+}
+
+fn replace(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [from, to] = arity::<2>(args)?;
+    Ok(Value::String(text(value).replace(&value_to_string(from), &value_to_string(to))))
+}
+
+fn starts_with(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [prefix] = arity::<1>(args)?;
+    Ok(Value::Bool(text(value).starts_with(&value_to_string(prefix))))
+}
+
+fn ends_with(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [suffix] = arity::<1>(args)?;
+    Ok(Value::Bool(text(value).ends_with(&value_to_string(suffix))))
+}
+
+fn string_contains(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [part] = arity::<1>(args)?;
+    Ok(Value::Bool(text(value).contains(&value_to_string(part))))
+}
+
+fn string_index_of(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [part] = arity::<1>(args)?;
+    let s = text(value);
+    Ok(char_position(s, s.find(&value_to_string(part))))
+}
+
+fn last_index_of(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [part] = arity::<1>(args)?;
+    let s = text(value);
+    Ok(char_position(s, s.rfind(&value_to_string(part))))
+}
+
+fn split(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [separator] = arity::<1>(args)?;
+    let separator = value_to_string(separator);
+    let parts = text(value).split(separator.as_str()).map(|part| Value::String(part.to_string()));
+    Ok(Value::Array(parts.collect()))
+}
+
+fn concat(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [other] = arity::<1>(args)?;
+    Ok(Value::String(format!("{}{}", text(value), value_to_string(other))))
+}
+
+fn equals_ignore_case(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [other] = arity::<1>(args)?;
+    Ok(Value::Bool(other.is_string() && text(value).to_lowercase() == text(other).to_lowercase()))
+}
+
+/// 整个字符串匹配正则表达式
+fn matches(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [pattern] = arity::<1>(args)?;
+    let regex = regex::Regex::new(&format!("^(?:{})$", value_to_string(pattern))).map_err(|err| err.to_string())?;
+    Ok(Value::Bool(regex.is_match(text(value))))
+}
+
+fn list(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or_default()
+}
+
+fn list_size(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::from(list(value).len()))
+}
+
+fn list_is_empty(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::Bool(list(value).is_empty()))
+}
+
+fn list_contains(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [item] = arity::<1>(args)?;
+    Ok(Value::Bool(list(value).iter().any(|element| equals(element, item))))
+}
+
+fn list_index_of(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [item] = arity::<1>(args)?;
+    let index = list(value).iter().position(|element| equals(element, item));
+    Ok(index.map(Value::from).unwrap_or_else(|| Value::from(-1)))
+}
+
+fn map_size(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::from(value.as_object().map_or(0, |map| map.len())))
+}
+
+fn map_is_empty(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::Bool(value.as_object().is_none_or(|map| map.is_empty())))
+}
+
+fn key_set(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    let keys = value.as_object().into_iter().flat_map(|map| map.keys()).map(|key| Value::String(key.clone()));
+    Ok(Value::Array(keys.collect()))
+}
+
+fn values(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::Array(value.as_object().into_iter().flat_map(|map| map.values()).cloned().collect()))
+}
+
+fn contains_key(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [key] = arity::<1>(args)?;
+    Ok(Value::Bool(value.get(value_to_string(key)).is_some()))
+}
+
+fn contains_value(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [item] = arity::<1>(args)?;
+    let found = value.as_object().is_some_and(|map| map.values().any(|element| equals(element, item)));
+    Ok(Value::Bool(found))
+}
+
+fn to_string(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(Value::String(value_to_string(value)))
+}
+
+/// 返回值本身；输出 `$value.safe()` 或通过 `#set`、宏参数传递后输出时不做自动转义
+fn safe(value: &Value, args: &[Value]) -> Result<Value, String> {
+    arity::<0>(args)?;
+    Ok(value.clone())
+}
+
+fn equals_method(value: &Value, args: &[Value]) -> Result<Value, String> {
+    let [other] = arity::<1>(args)?;
+    Ok(Value::Bool(equals(value, other)))
+}
@@ -1,2 +1,49 @@
-pub mod expression;
+use std::fmt;
+
+pub mod ast;
+pub mod expr_parser;
 pub mod expr_eval;
+pub mod methods;
+
+/// 表达式解析或计算出错，offset 为出错位置相对表达式开头的字节偏移；
+/// 严格模式下引用未定义时 undefined 为引用的名称；超出限制时 limit 为 true，非严格模式下也返回错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+    pub message: String,
+    pub offset: usize,
+    pub undefined: Option<String>,
+    pub limit: bool,
+}
+
+impl ExpressionError {
+    pub fn new(message: impl Into<String>, offset: usize) -> Self {
+        ExpressionError {
+            message: message.into(),
+            offset,
+            undefined: None,
+            limit: false,
+        }
+    }
+
+    pub fn limit(message: impl Into<String>) -> Self {
+        ExpressionError {
+            limit: true,
+            ..ExpressionError::new(message, 0)
+        }
+    }
+
+    pub fn undefined(name: String) -> Self {
+        ExpressionError {
+            message: format!("undefined reference ${}", name),
+            offset: 0,
+            undefined: Some(name),
+            limit: false,
+        }
+    }
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
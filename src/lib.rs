@@ -1,76 +1,77 @@
-use crate::token::token_parse;
+use lazy_static::lazy_static;
 use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::ops::Add;
+
+pub mod cache;
+
+pub mod config;
+
+pub mod context;
+
+pub mod engine;
+
+pub mod error;
+
+pub mod escape;
+
+pub mod event;
 
 pub mod expression;
 
+pub mod loader;
+
 pub mod token;
 
 pub mod parse;
 
+pub use crate::cache::{CachePolicy, CacheStats};
+pub use crate::config::{Escape, RenderConfig, SetScope, SpaceGobbling};
+pub use crate::context::{Context, FrameKind, Variables};
+pub use crate::engine::{Template, VelocityEngine, VelocityEngineBuilder};
+pub use crate::error::{Location, VelocityError};
+pub use crate::event::{EventHandlers, IncludeHandler, InvalidReferenceHandler, MethodExceptionHandler, ReferenceInsertionHandler};
+pub use crate::loader::{FileResourceLoader, MemoryResourceLoader, ResourceLoader, StaticResourceLoader};
 
-pub fn render_default_path(path:&str)-> Result<String, String> {
+
+lazy_static! {
+    // 兼容原有的函数式接口，使用默认配置
+    static ref DEFAULT_ENGINE: VelocityEngine = VelocityEngine::default();
+}
+
+
+pub fn render_default_path(path:&str)-> Result<String, VelocityError> {
     let mut content:HashMap<String, Value> = HashMap::new();
-    match read_file(path) {
-        Ok(template) => match render(template.as_str(),&mut content) {
-            Ok(content) => Ok(content),
-            Err(error) => Err(error),
-        },
-        Err(error) => Err(error),
-    }
+    render_from_path(path, &mut content)
 }
 
-pub fn render_default(template:&str)-> Result<String, String> {
+pub fn render_default(template:&str)-> Result<String, VelocityError> {
     let mut content:HashMap<String, Value> = HashMap::new();
     render(template, &mut content)
 }
 
-pub fn render(template: &str, content: &mut HashMap<String, Value>) -> Result<String, String> {
-    let tokens_result = token_parse::get_tokens(template);
-    match tokens_result {
-        Ok(tokens) => {
-            let output = token_parse::parse_tokens(&tokens, content);
-            if let Some(output) = output {
-                return Ok(output);
-            }
-            Ok(String::new())
-        }
-        Err(error) => Err(error.to_string()),
-    }
+pub fn render(template: &str, content: &mut HashMap<String, Value>) -> Result<String, VelocityError> {
+    DEFAULT_ENGINE.render(template, content)
 }
 
-pub fn render_from_path(path: &str, content: &mut HashMap<String, Value>, ) -> Result<String, String> {
-    match read_file(path) {
-        Ok(template) => match render(template.as_str(), content) {
-            Ok(content) => Ok(content),
-            Err(error) => Err(error),
-        },
-        Err(error) => Err(error),
-    }
+pub fn render_from_path(path: &str, content: &mut HashMap<String, Value>, ) -> Result<String, VelocityError> {
+    let template = read_file(path)?;
+    DEFAULT_ENGINE.render_named(path, template.as_str(), content)
 }
 
-pub fn render_from_object<T: Serialize>(template: &str, obj: &T) -> Result<String, String> {
-    let mut context_result = object_to_hashmap(obj);
-    match context_result {
-        Ok(mut context_map) => match render(template, &mut context_map) {
-            Ok(content) => Ok(content),
-            Err(error) => Err(error),
-        },
-        Err(error) => Err(error),
-    }
+pub fn render_from_object<T: Serialize>(template: &str, obj: &T) -> Result<String, VelocityError> {
+    DEFAULT_ENGINE.render_object(template, obj)
 }
 
-pub fn object_to_hashmap<T: Serialize>(obj: &T) -> Result<HashMap<String, Value>, String> {
+pub fn object_to_hashmap<T: Serialize>(obj: &T) -> Result<HashMap<String, Value>, VelocityError> {
     match serde_json::to_value(obj) {
         Ok(Value::Object(map)) => Ok(map.into_iter().collect()),
-        Ok(_) => Err("The serialized object is not a JSON object.".to_string()),
-        Err(err) => Err(format!("Error serializing object: {}", err)),
+        Ok(_) => Err(VelocityError::Context { message: "The serialized object is not a JSON object.".to_string() }),
+        Err(err) => Err(VelocityError::Context { message: format!("Error serializing object: {}", err) }),
     }
 }
 
-pub fn read_file(file_path: &str) -> Result<String, String> {
+pub fn read_file(file_path: &str) -> Result<String, VelocityError> {
     std::fs::read_to_string(file_path)
-        .map_err(|err| format!("Failed to read file '{}': {}", file_path, err))
+        .map_err(|err| VelocityError::Io { path: file_path.to_string(), source: err })
 }
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use crate::error::VelocityError;
+
+/// 按名称加载模板内容，`#parse`、`#include` 以及 `VelocityEngine::render_template` 通过它读取模板
+pub trait ResourceLoader: Debug + Send + Sync {
+    /// 读取模板内容，模板不存在时返回 `VelocityError::NotFound`
+    fn load(&self, name: &str) -> Result<String, VelocityError>;
+
+    /// 模板最后修改时间，用于判断缓存是否过期；无法获取时返回 None
+    fn last_modified(&self, name: &str) -> Option<SystemTime>;
+
+    fn exists(&self, name: &str) -> bool;
+}
+
+/// 共享的加载器，例如在引擎外继续更新 `Arc<MemoryResourceLoader>` 中的模板
+impl<T: ResourceLoader + ?Sized> ResourceLoader for Arc<T> {
+    fn load(&self, name: &str) -> Result<String, VelocityError> {
+        (**self).load(name)
+    }
+
+    fn last_modified(&self, name: &str) -> Option<SystemTime> {
+        (**self).last_modified(name)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        (**self).exists(name)
+    }
+}
+
+fn not_found(name: &str) -> VelocityError {
+    VelocityError::NotFound { name: name.to_string() }
+}
+
+/// 从一个或多个目录读取模板文件，名称是相对于目录的路径，按添加顺序查找
+#[derive(Debug, Clone)]
+pub struct FileResourceLoader {
+    paths: Vec<PathBuf>,
+}
+
+impl FileResourceLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileResourceLoader { paths: vec![root.into()] }
+    }
+
+    /// 按顺序在多个目录中查找模板
+    pub fn with_paths<I, P>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        FileResourceLoader { paths: paths.into_iter().map(Into::into).collect() }
+    }
+
+    /// 追加一个查找目录
+    pub fn add_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// 第一个存在该模板的目录中的文件路径。名称只能是目录下的相对路径，
+    /// 绝对路径、`..` 跳出目录以及通过符号链接指向目录外的文件都按不存在处理
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let name = normalize(name)?;
+        self.paths.iter().find_map(|root| {
+            let path = root.join(&name);
+            if !path.is_file() {
+                return None;
+            }
+            let inside = path.canonicalize().ok()?.starts_with(root.canonicalize().ok()?);
+            inside.then_some(path)
+        })
+    }
+}
+
+/// 去掉模板名称中的 `.`，消去 `..`；不是相对路径或跳出根目录时返回 None
+fn normalize(name: &str) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+impl Default for FileResourceLoader {
+    /// 以当前工作目录为模板根目录
+    fn default() -> Self {
+        FileResourceLoader::new(".")
+    }
+}
+
+impl ResourceLoader for FileResourceLoader {
+    fn load(&self, name: &str) -> Result<String, VelocityError> {
+        let path = self.find(name).ok_or_else(|| not_found(name))?;
+        std::fs::read_to_string(&path).map_err(|err| VelocityError::Io {
+            path: path.display().to_string(),
+            source: err,
+        })
+    }
+
+    fn last_modified(&self, name: &str) -> Option<SystemTime> {
+        self.find(name)?.metadata().ok()?.modified().ok()
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+}
+
+/// 保存在内存中的模板，适合测试或模板存放在数据库中的场景；可以在使用过程中更新
+#[derive(Debug, Default)]
+pub struct MemoryResourceLoader {
+    templates: RwLock<HashMap<String, (String, SystemTime)>>,
+}
+
+impl MemoryResourceLoader {
+    pub fn new() -> Self {
+        MemoryResourceLoader::default()
+    }
+
+    /// 添加或替换模板，同时更新修改时间
+    pub fn insert(&self, name: impl Into<String>, template: impl Into<String>) {
+        let name = name.into();
+        let mut templates = self.templates.write().unwrap();
+        // 保证修改时间递增，连续两次更新也能让缓存失效
+        let mut modified = SystemTime::now();
+        if let Some((_, previous)) = templates.get(&name) {
+            if modified <= *previous {
+                modified = *previous + Duration::from_nanos(1);
+            }
+        }
+        templates.insert(name, (template.into(), modified));
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.templates.write().unwrap().remove(name);
+    }
+}
+
+impl From<HashMap<String, String>> for MemoryResourceLoader {
+    fn from(templates: HashMap<String, String>) -> Self {
+        let loader = MemoryResourceLoader::new();
+        for (name, template) in templates {
+            loader.insert(name, template);
+        }
+        loader
+    }
+}
+
+impl ResourceLoader for MemoryResourceLoader {
+    fn load(&self, name: &str) -> Result<String, VelocityError> {
+        let templates = self.templates.read().unwrap();
+        templates.get(name).map(|(template, _)| template.clone()).ok_or_else(|| not_found(name))
+    }
+
+    fn last_modified(&self, name: &str) -> Option<SystemTime> {
+        self.templates.read().unwrap().get(name).map(|(_, modified)| *modified)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.templates.read().unwrap().contains_key(name)
+    }
+}
+
+/// 编译进程序的模板，例如：
+/// `StaticResourceLoader::new(&[("page.vm", include_str!("templates/page.vm"))])`
+#[derive(Debug, Clone, Copy)]
+pub struct StaticResourceLoader {
+    templates: &'static [(&'static str, &'static str)],
+}
+
+impl StaticResourceLoader {
+    pub const fn new(templates: &'static [(&'static str, &'static str)]) -> Self {
+        StaticResourceLoader { templates }
+    }
+
+    fn find(&self, name: &str) -> Option<&'static str> {
+        self.templates.iter().find(|(key, _)| *key == name).map(|(_, template)| *template)
+    }
+}
+
+impl ResourceLoader for StaticResourceLoader {
+    fn load(&self, name: &str) -> Result<String, VelocityError> {
+        self.find(name).map(str::to_string).ok_or_else(|| not_found(name))
+    }
+
+    /// 内容不会变化
+    fn last_modified(&self, _name: &str) -> Option<SystemTime> {
+        None
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+}
+
+/// 按顺序查找的多个加载器，由 VelocityEngineBuilder 在添加了多个加载器时创建
+#[derive(Debug)]
+pub(crate) struct LoaderChain {
+    pub(crate) loaders: Vec<Arc<dyn ResourceLoader>>,
+}
+
+impl LoaderChain {
+    fn find(&self, name: &str) -> Option<&Arc<dyn ResourceLoader>> {
+        self.loaders.iter().find(|loader| loader.exists(name))
+    }
+}
+
+impl ResourceLoader for LoaderChain {
+    fn load(&self, name: &str) -> Result<String, VelocityError> {
+        self.find(name).ok_or_else(|| not_found(name))?.load(name)
+    }
+
+    fn last_modified(&self, name: &str) -> Option<SystemTime> {
+        self.find(name)?.last_modified(name)
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+}
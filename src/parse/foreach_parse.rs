@@ -1,208 +1,170 @@
-use std::collections::HashMap;
-use std::fmt::format;
-use serde::Deserializer;
+use std::fmt;
 use serde_json::{Map, Number, Value};
-use crate::parse::{text_parse, update_content, variable_parse};
-use crate::token::token_parse::{parse_token, Tokenizer};
+use crate::context::{Context, FrameKind, Variables};
+use crate::error::VelocityError;
+use crate::expression::ast::Expr;
+use crate::expression::expr_eval;
+use crate::token::token_parse::{self, BreakScope, Interrupt, RenderState, Tokenizer};
+
+/// 循环状态 `$foreach`
+const FOREACH: &str = "foreach";
+
+/// 循环的内容：Map 按键值对循环，数组按元素循环，size 为元素个数
+enum Items {
+    Map(Map<String, Value>),
+    List(Box<dyn Iterator<Item = Value>>, usize),
+}
+
+impl Items {
+    fn from_value(value: Value) -> Self {
+        match value {
+            Value::Object(map) => Items::Map(map),
+            Value::Array(list) => {
+                let size = list.len();
+                Items::List(Box::new(list.into_iter()), size)
+            }
+            _ => Items::List(Box::new(std::iter::empty()), 0),
+        }
+    }
 
-pub fn foreach_parse(token:&Tokenizer, context:&mut HashMap<String, Value>) -> Option<std::string::String> {
+    fn is_empty(&self) -> bool {
+        match self {
+            Items::Map(map) => map.is_empty(),
+            Items::List(_, size) => *size == 0,
+        }
+    }
+}
 
-    if let Tokenizer::Foreach { element,collection,children } = token {
-        let mut output = String::new();
+/// 可以循环 Map、数组、`[1..$n]` 范围以及字符串的字符，null 相当于空集合。
+/// 范围按上下界逐个生成元素，不会先生成整个数组。
+/// 循环变量写入单独的局部帧，循环结束后弹出，不会留在上下文中
+pub fn foreach_parse(token:&Tokenizer, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+
+    if let Tokenizer::Foreach { collection, .. } = token {
+        if let Expr::Range { start, end } = &collection.ast {
+            let items = match collection.range_in(start, end, context, state)? {
+                Some((start, end)) => {
+                    let size = usize::try_from(expr_eval::range_length(start, end)).unwrap_or(usize::MAX);
+                    Items::List(Box::new(expr_eval::range_items(start, end)), size)
+                }
+                None => Items::List(Box::new(std::iter::empty()), 0),
+            };
+            return render_foreach(token, items, context, state, out);
+        }
 
-        let element_key = if let Some(key) = variable_parse::extract_variable(&element) {
-            key.trim().to_string()
-        } else {
-            element.to_string()
+        let value = match collection.evaluate_in(context, state)? {
+            // 字符串按字符循环
+            Some(Value::String(s)) => Value::Array(s.chars().map(|c| Value::String(c.to_string())).collect()),
+            Some(value @ (Value::Bool(_) | Value::Number(_))) if state.config.strict => {
+                return Err(VelocityError::InvalidForeach {
+                    message: format!("cannot iterate over {}", expr_eval::type_name(&value)),
+                    location: state.location(collection.span.start),
+                });
+            }
+            Some(value) => value,
+            None => Value::Null,
         };
+        return render_foreach(token, Items::from_value(value), context, state, out);
+    }
 
+    Ok(())
+}
 
-        let collection_key = if let Some(key) = variable_parse::extract_variable(&collection) {
-            key.trim().to_string()
-        } else {
-            collection.to_string()
-        };
+fn render_foreach(token:&Tokenizer, items: Items, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
 
-        // log::debug!("element_key:{} collection_key:{}",element_key,collection_key);
-        // // log::debug!("context:{:#?}", context);
-        // log::debug!("----------children:{:#?}", children);
-        // log::debug!("parse_foreach key:{:?} element:{} collection:{}",element_key,element,collection);
-
-
-
-        if let Some(value) = context.get(&collection_key){
-
-            if let Value::Object(map) = value {
-                let size =  map.len();
-                let hash_map:serde_json::Map<String, Value>  = map.clone();
-
-                let mut index = 0;
-                let mut count    = 1;
-                let mut has_next = true;
-                let mut first = true;
-                let mut  last = true;
-                for mm in hash_map{
-                    let key = mm.0;
-                    let value = mm.1;
-
-                    if index==0 {
-                        first = true;
-                        last = false;
-                    }
-                    if index+1 == size {
-                        has_next = false;
-                        last = true;
-                    }
-
-                    update_content(context, format!("{}.count",&element_key).as_str(), Value::Number(Number::from(count)));
-                    update_content(context, format!("{}.first",&element_key).as_str(), Value::Bool(first));
-                    update_content(context, format!("{}.hasNext",&element_key).as_str(), Value::Bool(has_next));
-                    update_content(context, format!("{}.index",&element_key).as_str(), Value::Number(Number::from(index)));
-                    update_content(context, format!("{}.last",&element_key).as_str(), Value::Bool(last));
-
-                    let mut mv: serde_json::Map<String, Value> =Map::<String,Value>::new();
-                    mv.insert(key.to_string(),value.clone());
-
-                    update_content(context, &element_key, Value::Object(mv));
-                    update_content(context, format!("{}.key",&element_key).as_str(), Value::String(key.to_string()));
-                    update_content(context, format!("{}.value",&element_key).as_str(), value.clone());
-
-                    let mut child_output = String::new();
-
-                    if let Some(child) = children{
-                        for child_token in child {
-                            log::debug!("foreach children token:{:?}",&child_token);
-                            let result = parse_token(child_token,context);
-                            if let Some(text) = result {
-
-                                let value = variable_parse::normalize_variable_syntax(text.as_str(),context);
-
-                                if let Some(text) = text_parse::parse_string(&value){
-                                    if !text.trim().is_empty() {
-                                        child_output.push_str(&text);
-                                    }
-                                }else{
-                                    child_output.push_str(&value);
-                                }
-
-                                // child_output.push_str(&value);
-                            }
-                        }
-                    }
-
-                    if let Some(text) = text_parse::parse_string(&child_output){
-                        if !text.trim().is_empty() {
-                            output.push_str(&text);
-                        }
-                    }else{
-                        output.push_str(&child_output);
-                    }
-
-                    index += 1;
-                    count += 1;
-                }
+    if let Tokenizer::Foreach { else_children, .. } = token {
+        // null、空集合以及表达式有误时输出 #else 分支
+        if items.is_empty() {
+            return match else_children {
+                Some(children) => token_parse::parse_tokens(children, context, state, out),
+                None => Ok(()),
+            };
+        }
 
-            }else if let Value::Array(list) = value{
-                let items:Vec<Value> = list.clone();
-                let size = list.len();
+        // 嵌套循环中通过 $foreach.parent 访问外层循环
+        let parent = context.get(FOREACH).cloned();
+        context.push(FrameKind::Foreach);
+        state.foreach_depth += 1;
+        let output = render_items(token, items, parent, context, state, out);
+        state.foreach_depth -= 1;
+        context.pop();
+        return output;
+    }
 
-                // log::debug!("foreach context:{:?}",context);
-                let mut index = 0;
-                let mut count    = 1;
-                let mut has_next = true;
-                let mut first = true;
-                let mut  last = true;
-                for item in items {
-
-                    // log::debug!("foreach item:{:?}",&item);
-                    // log::debug!("foreach value is map:{:?}",item.is_object());
-
-
-                    if index==0 {
-                        first = true;
-                        last = false;
-                    }else{
-                        first = false;
-                    }
-                    if index+1 == size {
-                        has_next = false;
-                        last = true;
-                    }
-
-                    if item.is_object() {
-                        let map = item.as_object().unwrap();
-                        for (key,value) in map {
-                            update_content(context, format!("{}.{}", &element_key, &key).as_str(), value.clone());
-                        }
-                    }else if item.is_string() {
-                        update_content(context, &element_key,Value::String(item.to_string()));
-                    }else{
-                        update_content(context, &element_key, item);
-                    }
-
-
-                    update_content(context, format!("{}.count",&element_key).as_str(), Value::Number(Number::from(count)));
-                    update_content(context, format!("{}.first",&element_key).as_str(), Value::Bool(first));
-                    update_content(context, format!("{}.hasNext",&element_key).as_str(), Value::Bool(has_next));
-                    update_content(context, format!("{}.index",&element_key).as_str(), Value::Number(Number::from(index)));
-                    update_content(context, format!("{}.last",&element_key).as_str(), Value::Bool(last));
-
-                    // log::debug!("foreach context:{:#?}",&context);
-
-                    let mut child_output = String::new();
-                    if let Some(child) = children{
-                        for child_token in child {
-                            // log::debug!("foreach children token:{:?}",&child_token);
-                            let result = parse_token(child_token,context);
-                            if let Some(text) = result {
-
-                                log::debug!("foreach children text:{:?}",&text);
-                                let value = variable_parse::normalize_variable_syntax(text.as_str(),context);
-
-                                if let Some(text) = text_parse::parse_string(&value){
-                                    if !text.trim().is_empty() {
-                                        child_output.push_str(&text);
-                                    }
-                                }else{
-                                    child_output.push_str(&value);
-                                }
-
-                            }
-                        }
-                    }
-
-                    //
-                    if let Some(text) = text_parse::parse_string(&child_output){
-
-                        if !text.trim().is_empty() {
-                            output.push_str(&text);
-                        }
-                    }else{
-                        output.push_str(&child_output);
-                    }
-
-
-                    // log::debug!("output:{:#?}",&output);
-                    index += 1;
-                    count+=1;
-                }
+    Ok(())
+}
 
-            }
-        }
+fn render_items(token:&Tokenizer, items: Items, parent: Option<Value>, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+
+    if let Tokenizer::Foreach { element,children, .. } = token {
+        let element_key = element.as_str();
+
+        if let Items::Map(map) = items {
+            let size =  map.len();
 
-        if let Some(text) = text_parse::parse_string(&output){
-            if !text.trim().is_empty() {
-                return Some(text);
+            for (index, (key, value)) in map.into_iter().enumerate() {
+                update_loop_content(context, state, element_key, &parent, index, size);
+
+                let mut mv: serde_json::Map<String, Value> =Map::<String,Value>::new();
+                mv.insert(key.to_string(),value.clone());
+
+                context.define(element_key, Value::Object(mv));
+                context.define(format!("{}.key",element_key), Value::String(key.to_string()));
+                context.define(format!("{}.value",element_key), value);
+
+                token_parse::parse_tokens(children, context, state, out)?;
+                if interrupted(state) {
+                    break;
+                }
             }
-        }else{
-            return Some(output);
-        }
 
+        }else if let Items::List(list, size) = items{
+            for (index, item) in list.enumerate() {
+                context.define(element_key, item);
+                update_loop_content(context, state, element_key, &parent, index, size);
 
+                token_parse::parse_tokens(children, context, state, out)?;
+                if interrupted(state) {
+                    break;
+                }
+            }
 
+        }
     }
 
-    None
+    Ok(())
 }
 
+/// 循环体中遇到 `#break`、`#break($foreach)` 时结束循环；`#break($parse)`、`#stop` 继续向外传递
+fn interrupted(state: &mut RenderState) -> bool {
+    match state.interrupt {
+        Some(Interrupt::Break(BreakScope::Innermost | BreakScope::Foreach)) => {
+            state.interrupt = None;
+            true
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
 
+/// 写入循环状态 `$foreach`：index、count、first、last、hasNext 以及外层循环 parent；
+/// 开启 element_loop_variables 时还写入 item.index、item.count 等
+fn update_loop_content(context: &mut Context, state: &RenderState, element_key: &str, parent: &Option<Value>, index: usize, size: usize) {
+    let last = index + 1 == size;
+    let mut status = Map::new();
+    status.insert("index".to_string(), Value::Number(Number::from(index)));
+    status.insert("count".to_string(), Value::Number(Number::from(index + 1)));
+    status.insert("first".to_string(), Value::Bool(index == 0));
+    status.insert("last".to_string(), Value::Bool(last));
+    status.insert("hasNext".to_string(), Value::Bool(!last));
+
+    if state.config.element_loop_variables {
+        for (name, value) in &status {
+            context.define(format!("{}.{}", element_key, name), value.clone());
+        }
+    }
+    if let Some(parent) = parent {
+        status.insert("parent".to_string(), parent.clone());
+    }
+    context.define(FOREACH, Value::Object(status));
+}
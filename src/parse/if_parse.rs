@@ -1,61 +1,36 @@
-use std::collections::HashMap;
+use std::fmt;
 use serde_json::Value;
 use crate::expression::expr_eval;
-use crate::parse::{text_parse, variable_parse};
-use crate::token::token_parse;
-use crate::token::token_parse::{IfBranch, Tokenizer};
+use crate::context::Context;
+use crate::error::VelocityError;
+use crate::token::token_parse::{self, RenderState, Tokenizer};
 
 
-pub fn if_parse(token:&Tokenizer, context:&mut HashMap<std::string::String, Value>) -> Option<String> {
-    if let Tokenizer::If { branches} = token {
-
-        let mut output = String::new();
+pub fn if_parse(token:&Tokenizer, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+    if let Tokenizer::If { branches, .. } = token {
 
         for branch in branches {
-            if let IfBranch::If{condition,children,..} = branch {
-                let if_condition = variable_parse::normalize_variable_syntax(condition.as_str(), context);
-
-                if expr_eval::eval(&if_condition) {
-                    // println!("if expression:{:?}",expression);
-                    if let Some(child) = children {
-
-                        for child_token in child {
-                            let result = token_parse::parse_token(child_token, context);
-
-                            if let Some(text) = result {
-                                if let Some(value) = text_parse::parse_string(&text) {
-                                    if !value.trim().is_empty() {
-                                        output.push_str(&value);
-                                    }
-                                } else {
-                                    output.push_str(&text);
-                                }
-                            }
-                        }
-
-                    }
-
-                    if let Some(value) = text_parse::parse_string(&output) {
-                        if !value.trim().is_empty() {
-                            return Some(value);
-                        }
-                    } else {
-                        return Some(output);
+            let matched = match &branch.condition {
+                // 表达式有误时按 false 处理；严格模式下条件必须是布尔值
+                Some(condition) => match condition.evaluate_in(context, state)? {
+                    Some(Value::Bool(value)) => value,
+                    Some(value) if state.config.strict => {
+                        return Err(VelocityError::Expression {
+                            message: format!("condition must be a boolean, found {}", expr_eval::type_name(&value)),
+                            location: Some(state.location(condition.span.start)),
+                        });
                     }
-
-                }
-
-            }
-        }
-
-        if let Some(value) = text_parse::parse_string(&output) {
-            if !value.trim().is_empty() {
-                return Some(value);
+                    Some(value) => expr_eval::is_truthy(&value),
+                    None => false,
+                },
+                // #else
+                None => true,
+            };
+            if matched {
+                return token_parse::parse_tokens(&branch.children, context, state, out);
             }
-        } else {
-            return Some(output);
         }
 
     }
-    None
+    Ok(())
 }
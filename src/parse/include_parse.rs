@@ -0,0 +1,87 @@
+use std::fmt;
+use crate::context::Context;
+use crate::error::VelocityError;
+use crate::expression::expr_eval;
+use crate::token::token_parse::{self, BreakScope, Expression, Interrupt, RenderState, Span, Tokenizer};
+
+/// `#parse`：在当前上下文中渲染另一个模板
+pub fn template_parse(token:&Tokenizer, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+
+    if let Tokenizer::Parse { path, span } = token {
+        let name = match template_name(path, context, state, "parse")? {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let error = |message: String| VelocityError::Include {
+            path: name.clone(),
+            message,
+            location: state.location(span.start),
+        };
+        if state.parse_stack.contains(&name) {
+            return Err(error(format!("cyclic #parse: {} -> {}", state.parse_stack.join(" -> "), name)));
+        }
+        // 最外层模板不计入深度
+        if state.parse_stack.len() > state.config.max_include_depth {
+            return Err(error(format!("exceeded the maximum #parse depth of {}", state.config.max_include_depth)));
+        }
+
+        let template = state.engine.get_template(&name).map_err(|err| load_error(err, &name, span, state))?;
+
+        // 错误位置要指向被引入的模板
+        let parent_name = std::mem::replace(&mut state.name, name.clone());
+        let parent_source = std::mem::replace(&mut state.source, template.source_arc().clone());
+        state.parse_stack.push(name);
+        state.define_macros(template.tokens());
+
+        let output = token_parse::parse_tokens(template.tokens(), context, state, out);
+        // #break、#break($parse) 只结束被引入的模板
+        if let Some(Interrupt::Break(BreakScope::Innermost | BreakScope::Parse)) = state.interrupt {
+            state.interrupt = None;
+        }
+
+        state.parse_stack.pop();
+        state.name = parent_name;
+        state.source = parent_source;
+        return output;
+    }
+
+    Ok(())
+}
+
+/// `#include`：原样插入其他文件的内容，不做渲染
+pub fn include_parse(token:&Tokenizer, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+
+    if let Tokenizer::Include { paths, span } = token {
+        for path in paths {
+            if let Some(name) = template_name(path, context, state, "include")? {
+                let content = state.config.loader.load(&name).map_err(|err| load_error(err, &name, span, state))?;
+                token_parse::write(out, &content)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 找不到模板或读取失败时返回指向 `#parse`、`#include` 的错误，被引入模板中的语法错误保持不变
+fn load_error(error: VelocityError, name: &str, span: &Span, state: &RenderState) -> VelocityError {
+    let message = match error {
+        VelocityError::NotFound { .. } => "template not found".to_string(),
+        VelocityError::Io { path, source } => format!("failed to read '{}': {}", path, source),
+        error => return error,
+    };
+    VelocityError::Include {
+        path: name.to_string(),
+        message,
+        location: state.location(span.start),
+    }
+}
+
+/// 计算模板名称，支持字符串和变量；IncludeHandler 拒绝时返回 None
+fn template_name(path: &Expression, context: &Context, state: &RenderState, directive: &str) -> Result<Option<String>, VelocityError> {
+    let name = match path.evaluate_in(context, state)? {
+        Some(value) => expr_eval::value_to_string(&value),
+        None => path.text.clone(),
+    };
+    Ok(state.config.events.include(&name, &state.name, directive))
+}
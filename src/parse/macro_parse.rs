@@ -0,0 +1,110 @@
+use std::fmt;
+use serde_json::Value;
+use crate::context::{Context, FrameKind};
+use crate::error::VelocityError;
+use crate::expression::ast::Expr;
+use crate::parse::variable_parse;
+use crate::token::token_parse::{self, BreakScope, Expression, Interrupt, RenderState, Tokenizer};
+
+/// 块宏调用时，调用处的内容通过 `$bodyContent` 传给宏
+const BODY_CONTENT: &str = "bodyContent";
+
+pub fn macro_call_parse(token:&Tokenizer, context: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+
+    if let Tokenizer::MacroCall { name, args, body, span } = token {
+        let macro_name = name.trim_start_matches('@');
+        let definition = match state.macros.get(macro_name) {
+            Some(definition) => definition.clone(),
+            // 未定义的宏原样输出
+            None => return token_parse::write(out, &state.source[span.start..span.end]),
+        };
+
+        let error = |message: String| VelocityError::MacroCall {
+            name: macro_name.to_string(),
+            message,
+            location: state.location(span.start),
+        };
+        if args.len() > definition.params.len() {
+            return Err(error(format!("expected at most {} arguments, found {}", definition.params.len(), args.len())));
+        }
+        if state.macro_depth >= state.config.max_macro_depth {
+            return Err(error(format!("exceeded the maximum macro depth of {}", state.config.max_macro_depth)));
+        }
+
+        // 参数和 $bodyContent 都在调用处的上下文中计算；$bodyContent 中的引用已经按调用处转义过，输出时不再转义
+        let mut locals = Vec::new();
+        for (index, param) in definition.params.iter().enumerate() {
+            if let Some(arg) = args.get(index).or(param.default.as_ref()) {
+                locals.extend(bind_argument(&param.name, arg, context, state)?);
+            }
+        }
+        if let Some(body) = body {
+            let mut body_content = String::new();
+            token_parse::parse_tokens(body, context, state, &mut body_content)?;
+            // #break 只结束调用处的内容；其余的中断要结束宏所在的 #foreach 或模板，宏不再执行
+            match state.interrupt {
+                Some(Interrupt::Break(BreakScope::Innermost)) => state.interrupt = None,
+                Some(_) => return Ok(()),
+                None => {}
+            }
+            locals.push((BODY_CONTENT.to_string(), Value::String(body_content), true));
+        }
+
+        // 参数只在宏内部可见：写入宏调用的局部帧，没有传入的参数遮住外层的同名变量
+        context.push(FrameKind::Macro);
+        let names = definition.params.iter().map(|param| param.name.as_str()).chain([BODY_CONTENT]);
+        for name in names {
+            for (key, _) in context.prefixed(&format!("{}.", name)) {
+                context.define(key, Value::Null);
+            }
+            context.define(name, Value::Null);
+        }
+        for (key, value, safe) in locals {
+            if safe {
+                context.define_safe(key, value);
+            } else {
+                context.define(key, value);
+            }
+        }
+
+        // 宏的内容属于定义宏的模板，其中的错误位置和原样输出的内容都要取自该模板
+        let caller_name = std::mem::replace(&mut state.name, definition.template.clone());
+        let caller_source = std::mem::replace(&mut state.source, definition.source.clone());
+        state.macro_depth += 1;
+        let output = token_parse::parse_tokens(&definition.children, context, state, out);
+        // #break 只结束宏的内容
+        if let Some(Interrupt::Break(BreakScope::Innermost)) = state.interrupt {
+            state.interrupt = None;
+        }
+        state.macro_depth -= 1;
+        state.name = caller_name;
+        state.source = caller_source;
+        context.pop();
+        return output;
+    }
+
+    Ok(())
+}
+
+/// 在调用处计算参数的值以及值是否已经转义过；参数是 `$item` 这样的引用时，foreach 写入的 item.index 等变量一起传入
+fn bind_argument(param: &str, arg: &Expression, context: &Context, state: &RenderState) -> Result<Vec<(String, Value, bool)>, VelocityError> {
+    let mut entries = Vec::new();
+    let reference = match &arg.ast {
+        Expr::Reference { reference, .. } => Some(reference),
+        _ => None,
+    };
+    if let Some(value) = arg.evaluate_in(context, state)? {
+        if !value.is_null() {
+            let safe = reference.is_some_and(|reference| variable_parse::is_safe(reference, context));
+            entries.push((param.to_string(), value, safe));
+        }
+    }
+
+    if let Some(key) = reference.and_then(|reference| reference.dotted_name()) {
+        let prefix = format!("{}.", key);
+        for (k, v) in context.prefixed(&prefix) {
+            entries.push((format!("{}.{}", param, &k[prefix.len()..]), v, false));
+        }
+    }
+    Ok(entries)
+}
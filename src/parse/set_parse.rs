@@ -1,83 +1,51 @@
-use std::collections::HashMap;
-use serde_json::{Map, Number, Value};
-use crate::expression::{expr_eval};
-use crate::parse::{update_content, variable_parse};
-use crate::token::token_parse::Tokenizer;
-use evalexpr::{ Value as EvalValue,};
+use serde_json::Value;
+use crate::context::Context;
+use crate::error::VelocityError;
+use crate::expression::ast::Expr;
+use crate::parse::variable_parse;
+use crate::token::token_parse::{RenderState, Tokenizer};
 
 
 
-pub fn set_parse(token :&Tokenizer, context: &mut HashMap<String, Value>) {
+/// 表达式有误时不修改上下文，严格模式下返回错误
+pub fn set_parse(token :&Tokenizer, context: &mut Context, state: &RenderState) -> Result<(), VelocityError> {
 
-    if let Tokenizer::Set { key,value } = token {
-        // println!("key:{} vlaue:{}",key,value);
-        let k = variable_parse::extract_variable(&key);
-        let mut v = variable_parse::normalize_variable_syntax(value.as_str(),context);
-
-        // log::debug!("set key:{:?} value:{:?}",k,v);
-
-        if let Some(key) = k{
-
-            if expr_eval::is_valid_expression(v.as_str()) {
-                if let Ok(value) = expr_eval::eval_value(v.as_str()){
-                    match value {
-                        EvalValue::String(_) => {
-                            if let Ok(val) =value.as_string(){
-                                update_content(context, &key, Value::String(val));
-                            }
-                        }
-                        EvalValue::Float(_) => {
-                            if let Ok(val) =value.as_float(){
-                                update_content(context, &key, Value::from(val));
-                            }
-                        }
-                        EvalValue::Int(_) => {
-                            if let Ok(val) =value.as_int(){
-                                update_content(context, &key, Value::from(val));
-                            }
-                        }
-                        EvalValue::Boolean(_) => {
-                            if let Ok(val) =value.as_boolean(){
-                                update_content(context, &key, Value::Bool(val));
-                            }
-                        }
-                        _ =>{
+    if let Tokenizer::Set { key,value, .. } = token {
+        if let Some(result) = value.evaluate_in(context, state)? {
+            let safe = matches!(&value.ast, Expr::Reference { reference, .. } if variable_parse::is_safe(reference, context));
+            assign(context, key, result, safe);
+        }
+    }
+    Ok(())
+}
 
-                        }
+/// `#set($a.b.c = value)`：$a 是 Map 时写入嵌套的属性，否则写入名为 `a.b.c` 的变量；
+/// 赋值为 null 相当于删除变量。变量写入哪一层由 Context::set 决定，safe 表示值已经转义过
+fn assign(context: &mut Context, key: &str, value: Value, safe: bool) {
+    if let Some((root, path)) = key.split_once('.') {
+        if let Some(Value::Object(map)) = context.get_mut(root) {
+            let mut map = map;
+            let mut names = path.split('.').peekable();
+            while let Some(name) = names.next() {
+                if names.peek().is_none() {
+                    if value.is_null() {
+                        map.remove(name);
+                    } else {
+                        map.insert(name.to_string(), value);
                     }
                     return;
                 }
-            }
-
-            let new_value = if let Ok(parsed_bool) = v.parse::<bool>() {
-                Value::Bool(parsed_bool)
-            } else if let Ok(parsed_array) = serde_json::from_str::<Vec<Value>>(v.as_str()) {
-                let len = parsed_array.len();
-                update_content(context, format!("{}.size",&key).as_str(), Value::Number(Number::from(len)));
-                Value::Array(parsed_array)
-            } else if let Ok(map) = serde_json::from_str::<Map<String, Value>>(v.as_str()) {
-                let len = map.len();
-                update_content(context, format!("{}.size",&key).as_str(), Value::Number(Number::from(len)));
-                for (k,v) in &map {
-                    update_content(context, format!("{}.{}", &key,k.clone()).as_str(), v.clone());
+                match map.get_mut(name) {
+                    Some(Value::Object(child)) => map = child,
+                    _ => break,
                 }
-                Value::Object(map)
-            } else if let Ok(string) = v.parse::<String>(){
-                Value::String(string)
-            } else if let Ok(parsed_int) = v.parse::<i64>() {
-                Value::Number(Number::from(parsed_int))
-            } else if let Ok(parsed_float) = v.parse::<f64>() {
-                Value::Number(Number::from_f64(parsed_float).unwrap())
-            } else {
-                Value::String(v)
-            };
-
-            // log::debug!("---------------------------{:#?}",context);
-            update_content(context, &key, new_value);
-
+            }
         }
-
     }
 
+    if safe {
+        context.set_safe(key, value);
+    } else {
+        context.set(key, value);
+    }
 }
-
@@ -1,49 +1,89 @@
-use std::collections::HashMap;
-use lazy_static::lazy_static;
+use std::borrow::Cow;
+use std::fmt;
 use regex::Regex;
 use serde_json::Value;
+use crate::config::Escape;
+use crate::context::Context;
+use crate::error::VelocityError;
+use crate::escape::escape;
+use crate::expression::ast::{Reference, Segment};
+use crate::expression::expr_eval;
+use crate::token::token_parse::{self, RenderState, Tokenizer};
 
-lazy_static! {
-    // 同时匹配 ${} 包裹和 $ 开头的变量
-    static ref VARIABLE_RE: Regex = Regex::new(r"\$\{([a-zA-Z_][a-zA-Z0-9_\.]*)\}|(\$[a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-}
+/// 输出引用：值交给 ReferenceInsertionHandler 转换后输出；未定义或值为 null 时先交给 InvalidReferenceHandler，
+/// 没有给出值时严格模式下返回错误，否则原样输出为 `${name}`；`$!name`、`$!{name}` 输出空字符串
+pub fn reference_parse(token: &Tokenizer, context: &Context, state: &RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+    if let Tokenizer::Reference { name, quiet, reference, span } = token {
+        let events = &state.config.events;
+        let strict = state.config.strict && !*quiet;
+        let (value, error) = match state.evaluator(context, strict).resolve(reference) {
+            Ok(Some(value)) if !value.is_null() => (Some(value), None),
+            Ok(_) => (None, None),
+            Err(error) => (None, Some(error)),
+        };
+        let value = match value {
+            None if !*quiet => events.invalid_reference(name, || state.location(span.start)).map(Cow::Owned),
+            value => value,
+        };
 
-pub fn normalize_variable_syntax(input: &str, context: &mut HashMap<String, Value>) -> String {
-    // 使用正则表达式进行替换
-    VARIABLE_RE.replace_all(input, |caps: &regex::Captures| {
-        // 提取变量名
-        let key = if let Some(key) = caps.get(1) {
-            key.as_str().to_string()
-        } else if let Some(key) = caps.get(2) {
-            key.as_str()[1..].to_string()
-        } else {
-            String::new()
+        let escape = if is_safe(reference, context) { Escape::None } else { state.escape };
+        return match value {
+            // 没有注册处理器时不复制值
+            Some(value) if events.reference_insertion.is_empty() => write_value(out, &value, escape),
+            Some(value) => write_value(out, &events.reference_insert(name, value.into_owned()), escape),
+            None if *quiet => Ok(()),
+            None if strict => Err(match error {
+                Some(error) => state.expression_error(error, span.start),
+                None => VelocityError::Expression {
+                    message: format!("${} is null", name),
+                    location: Some(state.location(span.start)),
+                },
+            }),
+            None => token_parse::write(out, &format!("${{{}}}", name)),
         };
-        // 查找对应的值
-        match context.get(&key) {
-            Some(value) => match value {
-                Value::String(s) => s.to_string(),
-                Value::Number(n) => n.to_string(),
-                Value::Bool(b) => b.to_string(),
-                _ => format!("{}", value),
-            },
-            None => format!("${{{}}}", key),
-        }
-    })
-        .to_string()
+    }
+    Ok(())
+}
+
+/// 输出被 `\` 转义的引用：引用有值时 `\` 每两个输出一个，最后一个 `\` 把引用转义为原文；
+/// 没有值（未定义或为 null）时与 Velocity 相同，`\` 和引用都原样输出
+pub fn escaped_reference_parse(token: &Tokenizer, context: &Context, state: &RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
+    if let Tokenizer::EscapedReference { backslashes, text, reference, .. } = token {
+        let defined = matches!(state.evaluator(context, false).resolve(reference), Ok(Some(value)) if !value.is_null());
+        let backslashes = if defined { backslashes / 2 } else { *backslashes };
+        token_parse::write(out, &"\\".repeat(backslashes))?;
+        return token_parse::write(out, text);
+    }
+    Ok(())
+}
+
+/// 引用的值已经转义过：以 `.safe()` 结尾，或者是 `#set`、宏参数中标记为已转义的变量（包括 `$bodyContent`）
+pub(crate) fn is_safe(reference: &Reference, context: &Context) -> bool {
+    match reference.path.last() {
+        Some(Segment::Method { name, args }) if name == "safe" && args.is_empty() => true,
+        _ => reference.dotted_name().is_some_and(|name| context.is_safe(&name)),
+    }
+}
+
+/// 字符串不需要转义时直接写入，不会复制
+fn write_value(out: &mut dyn fmt::Write, value: &Value, mode: Escape) -> Result<(), VelocityError> {
+    match value {
+        Value::String(s) => token_parse::write(out, &escape(mode, s)),
+        value => token_parse::write(out, &escape(mode, &expr_eval::value_to_string(value))),
+    }
 }
 
 
 
 
-pub  fn extract_variable(input: &String) -> Option<String> {
+pub  fn extract_variable(input: &str) -> Option<String> {
     // 使用懒加载正则，避免每次调用都编译正则
     lazy_static::lazy_static! {
         static ref RE: Regex = Regex::new(r"^\$\{?(.*?)\}?$").unwrap();
     }
 
     // 尝试匹配并提取变量名
-    RE.captures(&input)
+    RE.captures(input)
         .and_then(|caps| caps.get(1).map(|m| m.as_str().trim().to_string()))
 }
 
@@ -51,16 +91,36 @@ pub  fn extract_variable(input: &String) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use serde_json::Value;
-    use crate::parse::variable_parse::normalize_variable_syntax;
+    use serde_json::{json, Value};
+    use crate::config::SetScope;
+    use crate::context::Context;
+    use crate::engine::VelocityEngine;
+    use crate::parse::variable_parse::reference_parse;
+    use crate::token::token_parse::{get_tokens, RenderState};
+
+    fn write(template: &str, context: &HashMap<String, Value>) -> String {
+        let engine = VelocityEngine::default();
+        let (globals, mut template_variables) = (HashMap::new(), context.clone());
+        let context = Context::new(&globals, &mut template_variables, SetScope::Global);
+        let state = RenderState::new(&engine, "<inline>", template.into());
+        let mut output = String::new();
+        reference_parse(&get_tokens(template).unwrap()[0], &context, &state, &mut output).unwrap();
+        output
+    }
 
     #[test]
-    fn test_normalize_variable_syntax() {
+    fn test_reference_parse() {
         let mut context:HashMap<String,Value> = HashMap::new();
         context.insert("foreach.index".to_string(), Value::Number(1.into()));
+        context.insert("user".to_string(), json!({"tags": ["a", {"b": "c"}]}));
 
-        let input = "$foreach.index ";
-        let result = normalize_variable_syntax(input, &mut context);
-        println!("{}", result); // 输出：1
+        assert_eq!(write("$foreach.index", &context), "1");
+        assert_eq!(write("${user.tags[1].b}", &context), "c");
+        assert_eq!(write("$user.tags.get(0)", &context), "a");
+        assert_eq!(write("$missing.name", &context), "${missing.name}");
+        assert_eq!(write("$!missing", &context), "");
+        context.insert("nothing".to_string(), Value::Null);
+        assert_eq!(write("$nothing", &context), "${nothing}");
+        assert_eq!(write("$!{nothing}", &context), "");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,384 @@
+use crate::error::VelocityError;
+use crate::expression::ast::Reference;
+use crate::expression::expr_parser;
+use crate::token::token_parse::{Span, TemplateSource};
+
+/// 支持的指令名称，其他 `#xxx(...)` 是宏调用，不带括号的 `#xxx` 按普通文本处理
+pub const DIRECTIVES: [&str; 11] = ["set", "if", "elseif", "else", "end", "foreach", "macro", "parse", "include", "break", "stop"];
+
+/// 以 `#end` 结尾的块指令
+pub const BLOCK_DIRECTIVES: [&str; 3] = ["if", "foreach", "macro"];
+
+/// 需要 `(...)` 参数的指令
+const DIRECTIVES_WITH_ARGS: [&str; 7] = ["set", "if", "elseif", "foreach", "macro", "parse", "include"];
+
+/// 参数可选的指令，`(` 必须紧跟在名称后面，例如 `#break($foreach)`
+const DIRECTIVES_WITH_OPTIONAL_ARGS: [&str; 1] = ["break"];
+
+/// 以 `#end` 结尾的指令，包括块宏调用 `#@name(...)`
+pub fn is_block_directive(name: &str) -> bool {
+    BLOCK_DIRECTIVES.contains(&name) || name.starts_with('@')
+}
+
+/// 词法单元：文本、引用、指令。注释在词法阶段直接丢弃
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lexeme {
+    Text {
+        text: String,
+        span: Span,
+    },
+    Reference {
+        name: String,
+        // `$!name` 未定义时输出空字符串
+        quiet: bool,
+        reference: Reference,
+        span: Span,
+    },
+    // `\$name`：前面有奇数个 `\`，渲染时根据引用是否有值决定输出几个 `\`
+    EscapedReference {
+        backslashes: usize,
+        // `$name` 的原文
+        text: String,
+        reference: Reference,
+        span: Span,
+    },
+    // `## ...`（line 为 true，包含行尾的换行符）和 `#* ... *#`，不输出，只用于处理周围的空白
+    Comment {
+        line: bool,
+        span: Span,
+    },
+    // 宏调用的 name 为宏名称，块宏调用以 `@` 开头
+    Directive {
+        name: String,
+        // 括号内的原始参数及其位置（不含括号）
+        args: Option<(String, Span)>,
+        span: Span,
+    },
+}
+
+/// 单遍扫描模板，生成词法单元
+pub fn tokenize(source: &TemplateSource) -> Result<Vec<Lexeme>, VelocityError> {
+    Lexer::new(source).run()
+}
+
+struct Lexer<'a> {
+    source: &'a TemplateSource<'a>,
+    input: &'a str,
+    pos: usize,
+    text: String,
+    text_span: Span,
+    lexemes: Vec<Lexeme>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a TemplateSource<'a>) -> Self {
+        Lexer {
+            source,
+            input: source.original,
+            pos: 0,
+            text: String::new(),
+            text_span: Span::new(0, 0),
+            lexemes: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<Lexeme>, VelocityError> {
+        while self.pos < self.input.len() {
+            let consumed = match self.peek(0) {
+                Some(b'#') => self.lex_hash()?,
+                Some(b'$') => self.lex_dollar(),
+                Some(b'\\') => self.lex_backslash(),
+                _ => false,
+            };
+            if consumed {
+                continue;
+            }
+
+            // 一次性读入到下一个 #、$ 或 \ 之前的普通文本
+            let start = self.pos;
+            let from = match self.peek(0) {
+                Some(b'#' | b'$' | b'\\') => start + 1,
+                _ => start,
+            };
+            let end = self.input[from..]
+                .find(['#', '$', '\\'])
+                .map(|i| from + i)
+                .unwrap_or(self.input.len());
+            self.push_text(start, end);
+            self.pos = end;
+        }
+        self.flush_text();
+        Ok(self.lexemes)
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + offset).copied()
+    }
+
+    fn push_text(&mut self, start: usize, end: usize) {
+        if self.text.is_empty() {
+            self.text_span.start = start;
+        }
+        self.text.push_str(&self.input[start..end]);
+        self.text_span.end = end;
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            self.lexemes.push(Lexeme::Text {
+                text: std::mem::take(&mut self.text),
+                span: self.text_span,
+            });
+        }
+    }
+
+    fn push_comment(&mut self, line: bool, start: usize, end: usize) {
+        self.flush_text();
+        self.lexemes.push(Lexeme::Comment { line, span: Span::new(start, end) });
+        self.pos = end;
+    }
+
+    /// 处理 `#` 开头的注释和指令，返回 false 表示 `#` 只是普通字符
+    fn lex_hash(&mut self) -> Result<bool, VelocityError> {
+        let start = self.pos;
+        match self.peek(1) {
+            // 行注释，连同换行符一起丢弃
+            Some(b'#') => {
+                let end = self.input[start..]
+                    .find('\n')
+                    .map(|i| start + i + 1)
+                    .unwrap_or(self.input.len());
+                self.push_comment(true, start, end);
+                return Ok(true);
+            }
+            // 块注释
+            Some(b'*') => {
+                return match self.input[start + 2..].find("*#") {
+                    Some(i) => {
+                        self.push_comment(false, start, start + 2 + i + 2);
+                        Ok(true)
+                    }
+                    None => Err(VelocityError::Syntax {
+                        message: "unterminated #* comment".to_string(),
+                        location: self.source.location(start),
+                    }),
+                };
+            }
+            // 不解析的内容 #[[ ... ]]#，原样输出
+            Some(b'[') if self.peek(2) == Some(b'[') => {
+                return match self.input[start + 3..].find("]]#") {
+                    Some(i) => {
+                        self.push_text(start + 3, start + 3 + i);
+                        self.pos = start + 3 + i + 3;
+                        Ok(true)
+                    }
+                    None => Err(VelocityError::Syntax {
+                        message: "unterminated #[[ block".to_string(),
+                        location: self.source.location(start),
+                    }),
+                };
+            }
+            _ => {}
+        }
+
+        let (name, block_call, mut end) = match scan_directive_name(self.input, start) {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        if block_call || !DIRECTIVES.contains(&name) {
+            return self.lex_macro_call(start, name, block_call, end);
+        }
+
+        let mut args = None;
+        if DIRECTIVES_WITH_ARGS.contains(&name) {
+            let open = skip_blank(self.input, end);
+            if self.input.as_bytes().get(open) != Some(&b'(') {
+                return Err(VelocityError::Syntax {
+                    message: format!("missing '(' after #{}", name),
+                    location: self.source.location(start),
+                });
+            }
+            let close = self.scan_args(open)?;
+            args = Some((self.input[open + 1..close].to_string(), Span::new(open + 1, close)));
+            end = close + 1;
+        } else if DIRECTIVES_WITH_OPTIONAL_ARGS.contains(&name) && self.input.as_bytes().get(end) == Some(&b'(') {
+            let close = self.scan_args(end)?;
+            args = Some((self.input[end + 1..close].to_string(), Span::new(end + 1, close)));
+            end = close + 1;
+        }
+
+        self.flush_text();
+        self.lexemes.push(Lexeme::Directive {
+            name: name.to_string(),
+            args,
+            span: Span::new(start, end),
+        });
+        self.pos = end;
+        Ok(true)
+    }
+
+    /// 宏调用 `#name(...)`，括号必须紧跟在名称后面，否则按普通文本处理
+    fn lex_macro_call(&mut self, start: usize, name: &str, block_call: bool, open: usize) -> Result<bool, VelocityError> {
+        if self.input.as_bytes().get(open) != Some(&b'(') {
+            return Ok(false);
+        }
+        let close = self.scan_args(open)?;
+        let name = if block_call { format!("@{}", name) } else { name.to_string() };
+
+        self.flush_text();
+        self.lexemes.push(Lexeme::Directive {
+            name,
+            args: Some((self.input[open + 1..close].to_string(), Span::new(open + 1, close))),
+            span: Span::new(start, close + 1),
+        });
+        self.pos = close + 1;
+        Ok(true)
+    }
+
+    /// 从 `(` 开始查找配对的 `)`，跳过字符串中的括号
+    fn scan_args(&self, open: usize) -> Result<usize, VelocityError> {
+        let bytes = self.input.as_bytes();
+        let mut depth = 0;
+        let mut i = open;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(i);
+                    }
+                }
+                quote @ (b'"' | b'\'') => match self.input[i + 1..].find(quote as char) {
+                    Some(len) => i += len + 1,
+                    None => {
+                        return Err(VelocityError::Syntax {
+                            message: "unterminated string literal".to_string(),
+                            location: self.source.location(i),
+                        })
+                    }
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+        Err(VelocityError::Syntax {
+            message: "missing ')'".to_string(),
+            location: self.source.location(open),
+        })
+    }
+
+    /// 转义：`\$name`、`\#if` 按原样输出 `$name`、`#if`。引用或指令前面的 `\` 每两个输出一个，
+    /// 剩下奇数个时引用或指令被转义，偶数个时照常处理；后面不是引用或指令时 `\` 是普通字符。
+    /// 与 Velocity 相同，被转义的引用没有值时 `\` 全部原样输出，留到渲染时处理
+    fn lex_backslash(&mut self) -> bool {
+        let start = self.pos;
+        let target = start + self.input[start..].bytes().take_while(|&c| c == b'\\').count();
+        let count = target - start;
+        let escaped_end = match self.input.as_bytes().get(target) {
+            Some(b'$') => match expr_parser::scan_reference(self.input, target) {
+                Some(scanned) if count % 2 == 1 => {
+                    self.flush_text();
+                    self.lexemes.push(Lexeme::EscapedReference {
+                        backslashes: count,
+                        text: self.input[target..scanned.end].to_string(),
+                        reference: scanned.reference,
+                        span: Span::new(start, scanned.end),
+                    });
+                    self.pos = scanned.end;
+                    return true;
+                }
+                scanned => scanned.map(|scanned| scanned.end),
+            },
+            Some(b'#') => scan_directive_name(self.input, target)
+                .filter(|(name, _, end)| DIRECTIVES.contains(name) || self.input.as_bytes().get(*end) == Some(&b'('))
+                .map(|(_, _, end)| end),
+            _ => None,
+        };
+        let end = match escaped_end {
+            Some(end) => end,
+            None => return false,
+        };
+
+        self.push_text(start, start + count / 2);
+        if count % 2 == 1 {
+            // 只转义 `#if` 本身，后面的参数仍按普通模板内容处理
+            self.push_text(target, end);
+            self.pos = end;
+        } else {
+            self.pos = target;
+        }
+        true
+    }
+
+    /// 处理 `$name`、`$a.b.c`、`${a.b.c}`、`$list[0]`、`$map.get("key")` 以及 `$!name`、`$!{name}`，
+    /// 返回 false 表示 `$` 只是普通字符
+    fn lex_dollar(&mut self) -> bool {
+        let start = self.pos;
+        let scanned = match expr_parser::scan_reference(self.input, start) {
+            Some(scanned) => scanned,
+            None => return false,
+        };
+
+        self.flush_text();
+        self.lexemes.push(Lexeme::Reference {
+            name: scanned.name,
+            quiet: scanned.quiet,
+            reference: scanned.reference,
+            span: Span::new(start, scanned.end),
+        });
+        self.pos = scanned.end;
+        true
+    }
+}
+
+/// 从 `#` 开始扫描 `#name`、`#{name}` 或 `#@name`，返回名称、是否块宏调用以及名称的结束位置
+fn scan_directive_name(input: &str, start: usize) -> Option<(&str, bool, usize)> {
+    let bytes = input.as_bytes();
+    let block_call = bytes.get(start + 1) == Some(&b'@');
+    let braced = bytes.get(start + 1) == Some(&b'{');
+    let name_start = if braced || block_call { start + 2 } else { start + 1 };
+    let name_end = scan_identifier(input, name_start);
+    if name_end == name_start {
+        return None;
+    }
+    let mut end = name_end;
+    if braced {
+        if bytes.get(end) != Some(&b'}') {
+            return None;
+        }
+        end += 1;
+    }
+    Some((&input[name_start..name_end], block_call, end))
+}
+
+fn is_identifier_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_identifier_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// 返回从 start 开始的标识符的结束位置，没有标识符时返回 start
+pub(crate) fn scan_identifier(input: &str, start: usize) -> usize {
+    let bytes = input.as_bytes();
+    if start >= bytes.len() || !is_identifier_start(bytes[start]) {
+        return start;
+    }
+    let mut end = start + 1;
+    while end < bytes.len() && is_identifier_char(bytes[end]) {
+        end += 1;
+    }
+    end
+}
+
+fn skip_blank(input: &str, start: usize) -> usize {
+    let bytes = input.as_bytes();
+    let mut i = start;
+    while i < bytes.len() && (bytes[i] == b' ' || bytes[i] == b'\t') {
+        i += 1;
+    }
+    i
+}
@@ -1 +1,7 @@
-pub mod token_parse;
\ No newline at end of file
+pub mod token_parse;
+
+pub mod lexer;
+
+pub mod parser;
+
+pub mod space_gobbling;
@@ -0,0 +1,423 @@
+use crate::config::SpaceGobbling;
+use crate::error::VelocityError;
+use crate::parse::variable_parse;
+use crate::token::lexer::{self, Lexeme};
+use crate::token::space_gobbling;
+use crate::token::token_parse::{BreakScope, Expression, IfBranch, MacroDefinition, MacroParam, Span, TemplateSource, Tokenizer};
+use std::sync::Arc;
+
+/// `#if`、`#foreach`、`#macro` 等块指令最多嵌套的层数，解析和渲染都是递归的，层数过多会栈溢出
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// 把模板解析为语法树，整个过程只扫描一遍模板
+pub fn parse(source: &TemplateSource, space_gobbling: SpaceGobbling) -> Result<Vec<Tokenizer>, VelocityError> {
+    let mut lexemes = lexer::tokenize(source)?;
+    space_gobbling::gobble(source.original, &mut lexemes, space_gobbling);
+    let mut parser = Parser {
+        source,
+        lexemes: lexemes.into_iter(),
+        depth: 0,
+        shared_source: None,
+    };
+
+    let (tokens, terminator) = parser.parse_block()?;
+    if let Some(terminator) = terminator {
+        return Err(VelocityError::UnmatchedBlock {
+            directive: format!("#{}", terminator.name),
+            location: source.location(terminator.span.start),
+        });
+    }
+    Ok(tokens)
+}
+
+/// 结束一个块的指令：#elseif、#else、#end
+struct Terminator {
+    name: String,
+    args: Option<(String, Span)>,
+    span: Span,
+}
+
+struct Parser<'a> {
+    source: &'a TemplateSource<'a>,
+    lexemes: std::vec::IntoIter<Lexeme>,
+    /// 当前所在块的嵌套层数
+    depth: usize,
+    /// 宏定义共享的模板源码，第一次定义宏时创建
+    shared_source: Option<Arc<str>>,
+}
+
+impl Parser<'_> {
+    /// 解析到块结束指令或模板结尾
+    fn parse_block(&mut self) -> Result<(Vec<Tokenizer>, Option<Terminator>), VelocityError> {
+        let mut tokens = Vec::new();
+        while let Some(lexeme) = self.lexemes.next() {
+            match lexeme {
+                Lexeme::Text { text, span } => tokens.push(Tokenizer::Text { text, span }),
+                Lexeme::Comment { .. } => {}
+                Lexeme::Reference { name, quiet, reference, span } => tokens.push(Tokenizer::Reference { name, quiet, reference, span }),
+                Lexeme::EscapedReference { backslashes, text, reference, span } => {
+                    tokens.push(Tokenizer::EscapedReference { backslashes, text, reference, span })
+                }
+                Lexeme::Directive { name, args, span } => {
+                    let token = match name.as_str() {
+                        "set" => self.parse_set(args, span),
+                        "if" => self.parse_if(args, span),
+                        "foreach" => self.parse_foreach(args, span),
+                        "macro" => self.parse_macro(args, span),
+                        "parse" | "include" => self.parse_include(&name, args, span),
+                        "break" => self.parse_break(args, span),
+                        "stop" => Ok(Tokenizer::Stop { span }),
+                        "elseif" | "else" | "end" => return Ok((tokens, Some(Terminator { name, args, span }))),
+                        _ => self.parse_macro_call(name, args, span),
+                    };
+                    tokens.push(token?);
+                }
+            }
+        }
+        Ok((tokens, None))
+    }
+
+    /// 解析 span 处的块指令的内容，嵌套超过 MAX_NESTING_DEPTH 层时报错
+    fn parse_nested(&mut self, span: Span) -> Result<(Vec<Tokenizer>, Option<Terminator>), VelocityError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(VelocityError::Syntax {
+                message: format!("blocks are nested deeper than {} levels", MAX_NESTING_DEPTH),
+                location: self.source.location(span.start),
+            });
+        }
+        self.depth += 1;
+        let block = self.parse_block();
+        self.depth -= 1;
+        block
+    }
+
+    /// 解析指令中的表达式，语法有误时返回指向出错位置的 `VelocityError::Syntax`
+    fn expression(&self, text: &str, span: Span) -> Result<Expression, VelocityError> {
+        Expression::new(text, span).map_err(|error| VelocityError::Syntax {
+            message: format!("invalid expression: {}", error.message),
+            location: self.source.location(span.start + error.offset),
+        })
+    }
+
+    fn parse_set(&mut self, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let (args, args_span) = args.unwrap_or_default();
+        let invalid = |message: &str| VelocityError::InvalidSet {
+            message: message.to_string(),
+            location: self.source.location(span.start),
+        };
+
+        let (left, right) = args.split_once('=').ok_or_else(|| invalid("expected '$name = value'"))?;
+        if !left.trim().starts_with('$') {
+            return Err(invalid("the left side of '=' must be a reference"));
+        }
+        let key = variable_parse::extract_variable(left.trim()).ok_or_else(|| invalid("invalid reference"))?;
+        let (value, value_span) = trim(right, args_span.start + left.len() + 1);
+        if value.is_empty() {
+            return Err(invalid("missing value after '='"));
+        }
+        let value = self.expression(value, value_span)?;
+
+        Ok(Tokenizer::Set { key, value, span })
+    }
+
+    fn parse_if(&mut self, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let mut branches = Vec::new();
+        let mut condition = self.condition(args)?;
+        let mut has_else = false;
+
+        loop {
+            let (children, terminator) = self.parse_nested(span)?;
+            let terminator = terminator.ok_or_else(|| VelocityError::UnmatchedBlock {
+                directive: "#if".to_string(),
+                location: self.source.location(span.start),
+            })?;
+            branches.push(IfBranch { condition: condition.take(), children });
+
+            match terminator.name.as_str() {
+                "end" => {
+                    return Ok(Tokenizer::If {
+                        branches,
+                        span: Span::new(span.start, terminator.span.end),
+                    })
+                }
+                name if has_else => {
+                    return Err(VelocityError::Syntax {
+                        message: format!("#{} after #else", name),
+                        location: self.source.location(terminator.span.start),
+                    })
+                }
+                "elseif" => {
+                    condition = self.condition(terminator.args)?;
+                }
+                _ => has_else = true,
+            }
+        }
+    }
+
+    /// `#if`、`#elseif` 的条件
+    fn condition(&self, args: Option<(String, Span)>) -> Result<Option<Expression>, VelocityError> {
+        args.map(|(text, span)| {
+            let (text, span) = trim(&text, span.start);
+            self.expression(text, span)
+        })
+        .transpose()
+    }
+
+    /// `#break`、`#break($foreach)`、`#break($parse)`
+    fn parse_break(&mut self, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let scope = match args.as_ref().map(|(text, _)| text.trim()) {
+            None => BreakScope::Innermost,
+            Some("$foreach") => BreakScope::Foreach,
+            Some("$parse") => BreakScope::Parse,
+            Some(_) => {
+                return Err(VelocityError::Syntax {
+                    message: "expected $foreach or $parse in #break".to_string(),
+                    location: self.source.location(span.start),
+                })
+            }
+        };
+        Ok(Tokenizer::Break { scope, span })
+    }
+
+    fn parse_foreach(&mut self, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let (args, args_span) = args.unwrap_or_default();
+        let (element, collection, collection_span) = split_foreach(&args, args_span.start).ok_or_else(|| VelocityError::InvalidForeach {
+            message: "expected '$item in $collection'".to_string(),
+            location: self.source.location(args_span.start),
+        })?;
+        let collection = self.expression(collection, collection_span)?;
+
+        let (children, mut terminator) = self.parse_nested(span)?;
+        let mut else_children = None;
+        if terminator.as_ref().is_some_and(|terminator| terminator.name == "else") {
+            let (children, end) = self.parse_nested(span)?;
+            else_children = Some(children);
+            terminator = end;
+        }
+
+        match terminator {
+            Some(terminator) if terminator.name == "end" => Ok(Tokenizer::Foreach {
+                element,
+                collection,
+                children,
+                else_children,
+                span: Span::new(span.start, terminator.span.end),
+            }),
+            Some(terminator) => Err(VelocityError::InvalidForeach {
+                message: format!("unexpected #{} inside #foreach", terminator.name),
+                location: self.source.location(terminator.span.start),
+            }),
+            None => Err(VelocityError::UnmatchedBlock {
+                directive: "#foreach".to_string(),
+                location: self.source.location(span.start),
+            }),
+        }
+    }
+
+    fn parse_macro(&mut self, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let (args, args_span) = args.unwrap_or_default();
+        let invalid = |message: &str, offset: usize| VelocityError::InvalidMacro {
+            message: message.to_string(),
+            location: self.source.location(offset),
+        };
+
+        let mut parts = split_arguments(&args, args_span.start).into_iter().peekable();
+        let name = parts
+            .next()
+            .filter(|part| lexer::scan_identifier(part.text, 0) == part.text.len())
+            .ok_or_else(|| invalid("expected a macro name", args_span.start))?;
+
+        let mut params: Vec<MacroParam> = Vec::new();
+        while let Some(part) = parts.next() {
+            let param = variable_parse::extract_variable(part.text)
+                .filter(|param| part.text.starts_with('$') && lexer::scan_identifier(param, 0) == param.len())
+                .ok_or_else(|| invalid("expected a parameter like '$name'", part.span.start))?;
+            if params.iter().any(|p| p.name == param) {
+                return Err(invalid("duplicate parameter", part.span.start));
+            }
+
+            let mut default = None;
+            if parts.peek().is_some_and(|next| next.text == "=") {
+                let equals = parts.next().map(|next| next.span.start).unwrap_or_default();
+                let value = parts.next().ok_or_else(|| invalid("missing default value after '='", equals))?;
+                default = Some(self.expression(value.text, value.span)?);
+            }
+            params.push(MacroParam { name: param, default });
+        }
+
+        let (children, terminator) = self.parse_nested(span)?;
+        match terminator {
+            Some(terminator) if terminator.name == "end" => Ok(Tokenizer::Macro {
+                definition: Arc::new(MacroDefinition {
+                    name: name.text.to_string(),
+                    params,
+                    children,
+                    template: self.source.name.to_string(),
+                    source: self.shared_source.get_or_insert_with(|| Arc::from(self.source.original)).clone(),
+                }),
+                span: Span::new(span.start, terminator.span.end),
+            }),
+            Some(terminator) => Err(invalid(&format!("unexpected #{} inside #macro", terminator.name), terminator.span.start)),
+            None => Err(VelocityError::UnmatchedBlock {
+                directive: "#macro".to_string(),
+                location: self.source.location(span.start),
+            }),
+        }
+    }
+
+    fn expressions(&self, args: Vec<Argument>) -> Result<Vec<Expression>, VelocityError> {
+        args.into_iter().map(|arg| self.expression(arg.text, arg.span)).collect()
+    }
+
+    /// `#parse("path")` 只接受一个参数，`#include` 可以有多个
+    fn parse_include(&mut self, name: &str, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let (args, args_span) = args.unwrap_or_default();
+        let mut paths = self.expressions(split_arguments(&args, args_span.start))?;
+        if paths.is_empty() || (name == "parse" && paths.len() > 1) {
+            return Err(VelocityError::Syntax {
+                message: format!("#{} expects {} template name", name, if name == "parse" { "one" } else { "at least one" }),
+                location: self.source.location(span.start),
+            });
+        }
+
+        if name == "parse" {
+            Ok(Tokenizer::Parse { path: paths.remove(0), span })
+        } else {
+            Ok(Tokenizer::Include { paths, span })
+        }
+    }
+
+    /// `#name(...)` 与块宏调用 `#@name(...) ... #end`
+    fn parse_macro_call(&mut self, name: String, args: Option<(String, Span)>, span: Span) -> Result<Tokenizer, VelocityError> {
+        let (args, args_span) = args.unwrap_or_default();
+        let args = split_arguments(&args, args_span.start);
+        if let Some(equals) = args.iter().find(|arg| arg.text == "=") {
+            return Err(VelocityError::Syntax {
+                message: format!("unexpected '=' in arguments of #{}", name),
+                location: self.source.location(equals.span.start),
+            });
+        }
+        let args = self.expressions(args)?;
+
+        if !name.starts_with('@') {
+            return Ok(Tokenizer::MacroCall { name, args, body: None, span });
+        }
+        let (children, terminator) = self.parse_nested(span)?;
+        match terminator {
+            Some(terminator) if terminator.name == "end" => Ok(Tokenizer::MacroCall {
+                name,
+                args,
+                body: Some(children),
+                span: Span::new(span.start, terminator.span.end),
+            }),
+            Some(terminator) => Err(VelocityError::Syntax {
+                message: format!("unexpected #{} inside #{}", terminator.name, name),
+                location: self.source.location(terminator.span.start),
+            }),
+            None => Err(VelocityError::UnmatchedBlock {
+                directive: format!("#{}", name),
+                location: self.source.location(span.start),
+            }),
+        }
+    }
+}
+
+/// 宏名称、宏参数或 `#parse`、`#include` 的参数
+struct Argument<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+/// 按空白和逗号拆分宏参数，字符串、括号内的内容不拆分，赋值用的 `=` 单独作为一项
+fn split_arguments(text: &str, start: usize) -> Vec<Argument<'_>> {
+    let bytes = text.as_bytes();
+    let mut parts = Vec::new();
+    let mut push = |from: usize, to: usize| {
+        parts.push(Argument {
+            text: &text[from..to],
+            span: Span::new(start + from, start + to),
+        })
+    };
+
+    let mut depth = 0usize;
+    let mut part_start = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                part_start.get_or_insert(i);
+                i = text[i + 1..].find(quote as char).map(|len| i + 1 + len).unwrap_or(bytes.len());
+            }
+            b'(' | b'[' | b'{' => {
+                part_start.get_or_insert(i);
+                depth += 1;
+            }
+            b')' | b']' | b'}' => {
+                part_start.get_or_insert(i);
+                depth = depth.saturating_sub(1);
+            }
+            b' ' | b'\t' | b'\r' | b'\n' | b',' if depth == 0 => {
+                if let Some(from) = part_start.take() {
+                    push(from, i);
+                }
+            }
+            b'=' if depth == 0
+                && bytes.get(i + 1) != Some(&b'=')
+                && !(i > 0 && matches!(bytes[i - 1], b'=' | b'!' | b'<' | b'>')) =>
+            {
+                if let Some(from) = part_start.take() {
+                    push(from, i);
+                }
+                push(i, i + 1);
+            }
+            _ => {
+                part_start.get_or_insert(i);
+            }
+        }
+        i += 1;
+    }
+    if let Some(from) = part_start {
+        push(from, bytes.len());
+    }
+    parts
+}
+
+/// 去掉首尾空白后的文本及其区间，start 为 text 在模板中的起始偏移
+fn trim(text: &str, start: usize) -> (&str, Span) {
+    let leading = text.len() - text.trim_start().len();
+    let trimmed = text.trim();
+    (trimmed, Span::new(start + leading, start + leading + trimmed.len()))
+}
+
+/// 拆分 `$item in $collection`，返回循环变量名、集合表达式的文本及其区间
+fn split_foreach(args: &str, start: usize) -> Option<(String, &str, Span)> {
+    let trimmed = args.trim_start();
+    let offset = args.len() - trimmed.len();
+    let rest = trimmed.strip_prefix('$')?;
+
+    let braced = rest.starts_with('{');
+    let name_start = if braced { 1 } else { 0 };
+    let name_end = lexer::scan_identifier(rest, name_start);
+    if name_end == name_start {
+        return None;
+    }
+    let element = rest[name_start..name_end].to_string();
+    let mut rest_start = name_end;
+    if braced {
+        rest.as_bytes().get(name_end).filter(|c| **c == b'}')?;
+        rest_start += 1;
+    }
+
+    let after_element = &rest[rest_start..];
+    let after_in = after_element.trim_start().strip_prefix("in")?;
+    if !after_element.starts_with(char::is_whitespace) || !after_in.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let collection_start = start + offset + 1 + rest_start + (after_element.len() - after_in.len());
+    let (collection, collection_span) = trim(after_in, collection_start);
+    if collection.is_empty() {
+        return None;
+    }
+    Some((element, collection, collection_span))
+}
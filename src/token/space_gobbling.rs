@@ -0,0 +1,171 @@
+use crate::config::SpaceGobbling;
+use crate::token::lexer::{self, Lexeme};
+
+/// 在词法单元上处理指令周围的空白，之后再交给语法分析
+pub fn gobble(source: &str, lexemes: &mut [Lexeme], mode: SpaceGobbling) {
+    match mode {
+        SpaceGobbling::None => {}
+        SpaceGobbling::Bc => gobble_bc(lexemes),
+        SpaceGobbling::Lines => gobble_lines(lexemes),
+        SpaceGobbling::Structured => {
+            // 缩进需要根据原始文本计算，所以在去掉指令行之前先算好
+            let indentation = block_indentation(source, lexemes);
+            gobble_lines(lexemes);
+            remove_indentation(source, lexemes, &indentation);
+        }
+    }
+}
+
+fn is_blank(text: &str) -> bool {
+    text.bytes().all(|c| c == b' ' || c == b'\t' || c == b'\r')
+}
+
+/// 指令前面到行首只有空白；`##` 注释连同换行符一起结束，后面是新的一行
+fn is_line_start(lexemes: &[Lexeme], index: usize) -> bool {
+    if index == 0 {
+        return true;
+    }
+    match &lexemes[index - 1] {
+        Lexeme::Comment { line: true, .. } => true,
+        Lexeme::Text { text, .. } => match text.rfind('\n') {
+            Some(line_break) => is_blank(&text[line_break + 1..]),
+            None => is_blank(text) && is_line_start(lexemes, index - 1),
+        },
+        _ => false,
+    }
+}
+
+/// 指令后面到行尾（或模板结尾）只有空白，或者只有 `##` 注释
+fn is_line_end(lexemes: &[Lexeme], index: usize) -> bool {
+    match lexemes.get(index + 1) {
+        None | Some(Lexeme::Comment { line: true, .. }) => true,
+        Some(Lexeme::Text { text, .. }) => match text.find('\n') {
+            Some(line_break) => is_blank(&text[..line_break]),
+            None => is_blank(text) && is_line_end(lexemes, index + 1),
+        },
+        _ => false,
+    }
+}
+
+/// 单独占一行的指令和注释，`##` 注释已经包含行尾的换行符
+fn alone_directives(lexemes: &[Lexeme]) -> Vec<bool> {
+    (0..lexemes.len())
+        .map(|index| {
+            let line_end = match lexemes[index] {
+                Lexeme::Comment { line: true, .. } => true,
+                Lexeme::Comment { .. } | Lexeme::Directive { .. } => is_line_end(lexemes, index),
+                _ => false,
+            };
+            line_end && is_line_start(lexemes, index)
+        })
+        .collect()
+}
+
+/// 去掉指令前的缩进
+fn trim_before(lexemes: &mut [Lexeme], index: usize) {
+    if index == 0 {
+        return;
+    }
+    if let Lexeme::Text { text, span } = &mut lexemes[index - 1] {
+        let keep = text.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        span.end -= text.len() - keep;
+        text.truncate(keep);
+    }
+}
+
+/// 去掉指令后到换行符（含）为止的空白
+fn trim_after(lexemes: &mut [Lexeme], index: usize) {
+    if let Some(Lexeme::Text { text, span }) = lexemes.get_mut(index + 1) {
+        let remove = text.find('\n').map(|i| i + 1).unwrap_or(text.len());
+        span.start += remove;
+        text.drain(..remove);
+    }
+}
+
+fn gobble_lines(lexemes: &mut [Lexeme]) {
+    let alone = alone_directives(lexemes);
+    for (index, alone) in alone.into_iter().enumerate() {
+        if alone {
+            trim_before(lexemes, index);
+            if !matches!(lexemes[index], Lexeme::Comment { line: true, .. }) {
+                trim_after(lexemes, index);
+            }
+        }
+    }
+}
+
+/// 兼容 Velocity 1.x，注释周围的空白保持不变
+fn gobble_bc(lexemes: &mut [Lexeme]) {
+    let alone = alone_directives(lexemes);
+    let line_end: Vec<bool> = (0..lexemes.len()).map(|index| is_line_end(lexemes, index)).collect();
+    for index in 0..lexemes.len() {
+        if let Lexeme::Directive { name, .. } = &lexemes[index] {
+            if alone[index] && name == "set" {
+                trim_before(lexemes, index);
+            }
+            if line_end[index] {
+                trim_after(lexemes, index);
+            }
+        }
+    }
+}
+
+/// 行首空白的宽度
+fn indent_width(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// 每个文本单元需要去掉的缩进宽度：所在各层块的内容相对于块指令多出来的缩进之和
+fn block_indentation(source: &str, lexemes: &[Lexeme]) -> Vec<usize> {
+    let alone = alone_directives(lexemes);
+    let mut stack: Vec<usize> = Vec::new();
+    let mut indentation = vec![0; lexemes.len()];
+
+    for (index, lexeme) in lexemes.iter().enumerate() {
+        match lexeme {
+            Lexeme::Directive { name, span, .. } if lexer::is_block_directive(name) => {
+                let delta = if alone[index] {
+                    let line_start = source[..span.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+                    let directive_indent = span.start - line_start;
+                    // 块内第一行非空内容的缩进
+                    let content_indent = source[span.end..]
+                        .split('\n')
+                        .skip(1)
+                        .find(|line| !is_blank(line))
+                        .map(indent_width)
+                        .unwrap_or(0);
+                    content_indent.saturating_sub(directive_indent)
+                } else {
+                    0
+                };
+                stack.push(delta);
+            }
+            Lexeme::Directive { name, .. } if name == "end" => {
+                stack.pop();
+            }
+            Lexeme::Text { .. } => indentation[index] = stack.iter().sum(),
+            _ => {}
+        }
+    }
+    indentation
+}
+
+fn remove_indentation(source: &str, lexemes: &mut [Lexeme], indentation: &[usize]) {
+    for (lexeme, width) in lexemes.iter_mut().zip(indentation) {
+        if *width == 0 {
+            continue;
+        }
+        if let Lexeme::Text { text, span } = lexeme {
+            let at_line_start = span.start == 0 || source.as_bytes()[span.start - 1] == b'\n';
+            let mut output = String::with_capacity(text.len());
+            for (i, line) in text.split_inclusive('\n').enumerate() {
+                if i == 0 && !at_line_start {
+                    output.push_str(line);
+                } else {
+                    output.push_str(&line[indent_width(line).min(*width)..]);
+                }
+            }
+            *text = output;
+        }
+    }
+}
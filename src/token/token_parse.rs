@@ -1,666 +1,387 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use lazy_static::lazy_static;
-use log::log;
-use regex::{escape, Regex};
-use serde::{Deserialize, Serialize};
-use serde_json::{Number, Value};
-use serde_json::Value::String as JsonString;
-use crate::parse::{foreach_parse, if_parse, set_parse, text_parse};
-
-#[derive(Debug,Clone)]
-pub enum Tokenizer{
-    Text {
-        text: String,
-    },
-    Set{
-        key: String,
-        value: String,
-    },
-    If{
-        branches: Vec<IfBranch>, // 条件分支，包括 If, ElseIf, Else
-    },
-    Foreach{
-        element: String,
-        collection: String,
-        children: Option<Vec<Tokenizer>>
-    }
+use std::fmt;
+use std::sync::Arc;
+use serde_json::Value;
+use crate::config::{Escape, RenderConfig};
+use crate::context::{Context, Variables};
+use crate::engine::VelocityEngine;
+use crate::error::{Location, VelocityError, INLINE_TEMPLATE_NAME};
+use crate::expression::ast::{Expr, Reference};
+use crate::expression::expr_eval::Evaluator;
+use crate::expression::{expr_parser, ExpressionError};
+use crate::parse::{foreach_parse, if_parse, include_parse, macro_parse, set_parse, text_parse, variable_parse};
+use crate::token::parser;
+
+/// 模板中的字节区间 [start, end)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
-#[derive(Debug,Clone)]
-pub enum IfBranch {
-    If {
-        condition: String,
-        children: Option<Vec<Tokenizer>>
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
     }
 }
 
-
-impl IfBranch {
-    pub fn new(condition: String, children: Vec<Tokenizer>) -> IfBranch {
-        IfBranch::If { condition, children: Some(children) }
-    }
+/// 指令中的表达式，例如 `#if(...)` 的条件、`#set` 的值，编译模板时解析成语法树
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub text: String,
+    pub span: Span,
+    pub ast: Expr,
 }
 
-impl Tokenizer {
-    pub fn new_text(text: &str) -> Self {
-        Tokenizer::Text{text:text.to_string()}
+impl Expression {
+    /// 解析表达式，语法有误时返回相对表达式开头的出错位置
+    pub fn new(text: &str, span: Span) -> Result<Self, ExpressionError> {
+        Ok(Expression {
+            text: text.to_string(),
+            span,
+            ast: expr_parser::parse(text)?,
+        })
     }
 
-    pub fn new_set(key: &str, value: &str) -> Self {
-        Tokenizer::Set { key:key.to_string(), value:value.to_string() }
+    /// 在上下文中计算表达式的值
+    pub fn evaluate(&self, context: &dyn Variables) -> Result<Value, ExpressionError> {
+        self.evaluate_with(Evaluator::new(context, false))
     }
 
-    pub fn new_if(branches: Vec<IfBranch>) -> Self {
-        Tokenizer::If{branches}
+    fn evaluate_with(&self, evaluator: Evaluator) -> Result<Value, ExpressionError> {
+        evaluator.evaluate(&self.ast)
     }
 
-    pub fn new_foreach(element: &str, collection: &str, children: Vec<Tokenizer>) -> Self {
-        Tokenizer::Foreach {
-            element:element.to_string(),
-            collection:collection.to_string(),
-            children:Some(children),
-        }
+    /// 渲染时计算表达式：出错时非严格模式返回 None，由指令按默认规则处理，严格模式返回指向表达式的错误
+    pub fn evaluate_in(&self, context: &Context, state: &RenderState) -> Result<Option<Value>, VelocityError> {
+        self.checked(state, self.evaluate_with(state.expression_evaluator(context, self.span.start)))
     }
-}
-
 
-#[derive(Debug, Serialize, Deserialize,Clone,PartialEq)]
-pub struct TokenPosition {
-    pub first_name: String,
-    pub first_start:usize,
-    pub first_end :usize,
-    pub last_name:String,
-    pub last_start:usize,
-    pub last_end:usize,
-
-}
-impl TokenPosition {
-
-    pub fn build(start:&NodePosition,end:&NodePosition) -> Self {
-        TokenPosition{
-            first_name:start.name.to_string(),
-            first_start:start.start,
-            first_end:start.end,
-            last_name:end.name.to_string(),
-            last_start:end.start,
-            last_end:end.end,
-        }
+    /// 渲染时计算 `[start..end]` 的上下界，出错时的处理与 evaluate_in 相同
+    pub fn range_in(&self, start: &Expr, end: &Expr, context: &Context, state: &RenderState) -> Result<Option<(i64, i64)>, VelocityError> {
+        self.checked(state, state.expression_evaluator(context, self.span.start).range_bounds(start, end))
     }
-    pub fn new_text(start:usize,end:usize) -> Self {
-        // log::debug!("new_text start:{} end:{}",start,end);
-        TokenPosition{
-            first_name:"#text".to_string(),
-            first_start:start,
-            first_end:start,
-            last_name:"#text".to_string(),
-            last_start:end,
-            last_end:end,
-        }
-    }
-    pub fn is_root(&self, tags: &[TokenPosition]) -> bool {
-        let start = self.first_start;
-        let end = self.last_start ;
-        for x in tags {
-            if x.first_start < start && start < x.last_start{
-                return false
-            }
-            if x.first_start <end && end < x.last_start {
-                return false
-            }
+
+    fn checked<T>(&self, state: &RenderState, result: Result<T, ExpressionError>) -> Result<Option<T>, VelocityError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if state.config.strict || error.limit => Err(state.expression_error(error, self.span.start)),
+            Err(_) => Ok(None),
         }
-        true
     }
 }
 
-#[derive(Debug, Serialize, Deserialize,Clone,PartialEq)]
-pub struct NodePosition {
-    pub name: String,
-    pub start:usize,
-    pub end :usize,
+#[derive(Debug,Clone)]
+pub enum Tokenizer{
+    Text {
+        text: String,
+        span: Span,
+    },
+    // $name、$a.b、${a.b}、$!name、$list[0]
+    Reference {
+        name: String,
+        quiet: bool,
+        reference: Reference,
+        span: Span,
+    },
+    Set{
+        key: String,
+        value: Expression,
+        span: Span,
+    },
+    If{
+        branches: Vec<IfBranch>, // 条件分支，包括 If, ElseIf, Else
+        span: Span,
+    },
+    Foreach{
+        element: String,
+        collection: Expression,
+        children: Vec<Tokenizer>,
+        // #foreach ... #else ... #end，集合为空或 null 时输出
+        else_children: Option<Vec<Tokenizer>>,
+        span: Span,
+    },
+    // #macro(name $a $b=default) ... #end
+    Macro{
+        definition: Arc<MacroDefinition>,
+        span: Span,
+    },
+    // #parse("path")
+    Parse{
+        path: Expression,
+        span: Span,
+    },
+    // #include("a.txt" "b.txt")
+    Include{
+        paths: Vec<Expression>,
+        span: Span,
+    },
+    // #name(arg1 arg2)，块宏调用 #@name(...) ... #end 带有 body
+    MacroCall{
+        name: String,
+        args: Vec<Expression>,
+        body: Option<Vec<Tokenizer>>,
+        span: Span,
+    },
+    // #break、#break($foreach)、#break($parse)
+    Break{
+        scope: BreakScope,
+        span: Span,
+    },
+    // #stop
+    Stop{
+        span: Span,
+    },
+    // `\$name`，backslashes 为前面 `\` 的个数（奇数），text 为 `$name` 的原文
+    EscapedReference {
+        backslashes: usize,
+        text: String,
+        reference: Reference,
+        span: Span,
+    },
 }
 
-impl NodePosition {
-    pub fn new(name: &str,start:usize,end:usize) -> Self {
-        NodePosition{
-            name:name.to_string(),
-            start,
-            end
+impl Tokenizer {
+    pub fn span(&self) -> Span {
+        match self {
+            Tokenizer::Text { span, .. }
+            | Tokenizer::Reference { span, .. }
+            | Tokenizer::Set { span, .. }
+            | Tokenizer::If { span, .. }
+            | Tokenizer::Foreach { span, .. }
+            | Tokenizer::Macro { span, .. }
+            | Tokenizer::Parse { span, .. }
+            | Tokenizer::Include { span, .. }
+            | Tokenizer::MacroCall { span, .. }
+            | Tokenizer::Break { span, .. }
+            | Tokenizer::Stop { span }
+            | Tokenizer::EscapedReference { span, .. } => *span,
         }
     }
 }
 
-
-lazy_static! {
-     pub static ref TAGS: Vec<&'static str> = {
-        let mut tags = Vec::new();
-        tags.push("#if");
-        tags.push("#elseif");
-        tags.push("#else");
-        tags.push("#foreach");
-        tags.push("#set");
-        tags.push("#end");
-        tags.push("##");
-        tags.push("<!--");
-        tags.push("-->");
-        tags.push("#*");
-        tags.push("*#");
-        tags
-    };
-
-
-        // 定义静态正则表达式模式，避免每次计算
-    pub static ref TAGS_PATTERN: Regex = {
-        // 生成正则表达式模式
-        let pattern = TAGS.iter()
-            .map(|tag| escape(*tag)) // 转义标签
-            .collect::<Vec<String>>()
-            .join("|"); // 使用 | 连接标签
-        // println!("pattern-------------{:?}" ,pattern);
-        Regex::new(&format!(r"({})", pattern)).unwrap() // 返回正则表达式
-    };
-
-    // 创建一个静态的 Mutex 包裹的 HashMap
-    static ref TOKEN_CACHE: Arc<Mutex<HashMap<String, Vec<Tokenizer>>>> = Arc::new(Mutex::new(HashMap::new()));
-
-    // 块注释 #* ... *#
-    static ref BLOCK_COMMENT_RE: Regex = Regex::new(r"#\*.*?\*#").unwrap();
-    // 匹配行尾注释 ## 开头到行尾（包括换行符）
-    static ref LINE_COMMENT_RE: Regex = Regex::new(r"(?m)^##.*\n?").unwrap(); // 匹配整行 ## 开头的注释
-
+/// `#if` / `#elseif` / `#else` 分支，`#else` 没有条件
+#[derive(Debug,Clone)]
+pub struct IfBranch {
+    pub condition: Option<Expression>,
+    pub children: Vec<Tokenizer>,
 }
 
-
-
-// set 方法：将键值对插入到静态的 HashMap 中
-fn set(key: String, value: Vec<Tokenizer>) {
-    let mut map = TOKEN_CACHE.lock().unwrap();
-    map.insert(key, value);
+/// `#break` 跳出的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakScope {
+    /// `#break`：最内层的 `#foreach`、宏或模板
+    Innermost,
+    /// `#break($foreach)`：最内层的 `#foreach`
+    Foreach,
+    /// `#break($parse)`：当前模板，在 `#parse` 引入的模板中只结束被引入的模板
+    Parse,
 }
 
-// get 方法：从静态的 HashMap 中获取值
-fn get(key: &str) -> Option<Vec<Tokenizer>> {
-    let map = TOKEN_CACHE.lock().unwrap();
-    map.get(key).cloned()
+/// `#break`、`#stop` 设置的中断。parse_tokens 遇到中断时不再渲染后面的内容，
+/// 直到对应的 `#foreach`、宏或模板清除它；`#stop` 结束整个渲染
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Break(BreakScope),
+    Stop,
 }
 
-fn remove_velocity_comments(template: &str) -> String {
-    // 移除块注释（#* ... *#）
-    let template = BLOCK_COMMENT_RE.replace_all(template, "");
-
-    // 移除整行以 ## 开头的注释，包括换行符
-    let template = LINE_COMMENT_RE.replace_all(&template, "");
-    // 返回处理后的字符串
-    template.to_string()
+/// `#macro` 定义的宏
+#[derive(Debug)]
+pub struct MacroDefinition {
+    pub name: String,
+    pub params: Vec<MacroParam>,
+    pub children: Vec<Tokenizer>,
+    /// 定义宏的模板名称及源码，宏可能定义在 `#parse` 引入的模板中，渲染宏的内容时用来定位
+    pub template: String,
+    pub source: Arc<str>,
 }
 
-pub fn get_tokens(template:&str) ->  Result<Vec<Tokenizer>,String>{
+/// 宏参数，`$b=default` 带有默认值
+#[derive(Debug, Clone)]
+pub struct MacroParam {
+    pub name: String,
+    pub default: Option<Expression>,
+}
 
-    let md5 = md5::compute(&template);
-    let key = format!("{:x}", md5);
 
-    let mut token_list = Vec::new();
-    if let Some(tokens) = get(&key) {
-        token_list = tokens;
-    }
+/// 模板源码及名称，用于把字节偏移转换为行号、列号
+pub struct TemplateSource<'a> {
+    pub name: &'a str,
+    pub original: &'a str,
+}
 
-    if !token_list.is_empty() {
-        return Ok(token_list);
+impl<'a> TemplateSource<'a> {
+    pub fn new(name: &'a str, original: &'a str) -> Self {
+        TemplateSource { name, original }
     }
 
-
-    let template = remove_velocity_comments(&template);
-
-
-    match parse_position(&template,0) {
-        Ok(token_position_list)=>{
-            match  position_to_tokenizer(&template,&token_position_list) {
-                Ok(tokens)=>{
-                    set(key, tokens.clone());
-                    Ok(tokens)
-                },
-                Err(e)=>{
-                    Err(e)
-                }
-            }
-        },
-        Err(e)=>{
-            Err(e)
-        }
+    pub fn location(&self, offset: usize) -> Box<Location> {
+        Box::new(Location::new(self.name, self.original, offset))
     }
 }
 
 
+/// 一次渲染过程中的状态：引擎及其配置、当前模板、已定义的宏、宏调用深度、`#parse` 嵌套的模板、引用输出的转义方式、
+/// `#foreach` 嵌套深度以及 `#break`、`#stop` 设置的中断
+pub struct RenderState<'a> {
+    pub engine: &'a VelocityEngine,
+    pub config: &'a RenderConfig,
+    pub name: String,
+    pub source: Arc<str>,
+    pub macros: HashMap<String, Arc<MacroDefinition>>,
+    pub macro_depth: usize,
+    pub parse_stack: Vec<String>,
+    pub escape: Escape,
+    pub foreach_depth: usize,
+    pub interrupt: Option<Interrupt>,
+}
 
-pub fn parse_position(template:&str,read_start_index:usize) -> Result<Vec<TokenPosition>,String>{
-
-    // 生成开始结束标签
-    let mut stack: Vec<NodePosition> = Vec::new(); // 用来存储开始标签的索引
-    let mut captures: Vec<(usize, &str)> = TAGS_PATTERN.find_iter(&template)
-        .map(|capture| (capture.start(), capture.as_str()))
-        .collect();
-
-    // println!("{:?}", captures);
-    let mut token_position_list = Vec::new();
-
-    for (first_start, first_name) in captures {
-        let first_name = first_name;
-        let first_start = first_start;
-        let first_end = first_start + first_name.len();
-
-        let node_position = NodePosition::new(first_name,first_start,first_end);
-
-        if first_name == "#end" {
-            if let Some(position) = stack.pop() {
-                // log::debug!("start:{:?}   end:{:?}",position, node_position);
-                let token_position= TokenPosition::build(&position,&node_position);
-                token_position_list.push(token_position);
-            } else {
-                // 如果没有找到匹配的开始标签，说明不匹配，报错
-                return Err(format!("Unmatched #end at index {}", first_name));
-            }
-        }else if first_name == "#else" {
-            if let Some(position) = stack.pop() {
-                // log::debug!("start:{:?}   end:{:?}",position, node_position);
-                let token_position= TokenPosition::build(&position,&node_position);
-                token_position_list.push(token_position);
-                stack.push(node_position);
-            }
-        }else if first_name == "#elseif" {
-            if let Some(position) = stack.pop() {
-                // log::debug!("start:{:?}   end:{:?}",position, node_position);
-                let token_position= TokenPosition::build(&position,&node_position);
-                token_position_list.push(token_position);
-                stack.push(node_position);
-            }
-        }else if first_name == "-->" {
-            if let Some(position) = stack.pop() {
-                // log::debug!("start:{:?}   end:{:?}",position, node_position);
-                let token_position= TokenPosition::build(&position,&node_position);
-                token_position_list.push(token_position);
-                stack.push(node_position);
-            }
-        }else if first_name == "*#" {
-            if let Some(position) = stack.pop() {
-                // log::debug!("start:{:?}   end:{:?}",position, node_position);
-                let token_position= TokenPosition::build(&position,&node_position);
-                token_position_list.push(token_position);
-                stack.push(node_position);
-            }
-        }else if first_name =="#set" {
-            let last_text = &template[first_start..];
-            let last_start_option = find_tag_end(last_text, first_name);
-            let mut last_start = 0;
-            let mut last_end = 0;
-            if let Some(start) = last_start_option {
-                last_start = first_start + start;
-                last_end = last_start + 1;
-            }
-
-            let position = NodePosition::new("#set", last_start, last_end);
-            // log::debug!("set----start:{:?}   end:{:?}", node_position,position);
-            let token_position = TokenPosition::build(&node_position, &position);
-            token_position_list.push(token_position);
-
-
-            // let set_end = template[first_end..].find(')').map(|pos| first_end + pos);
-            // if let Some(end_pos) = set_end {
-            //     let position = NodePosition::new("#set",first_end+1,end_pos+1);
-            //     log::debug!("start:{:?}   end:{:?}", node_position,position);
-            //     let token_position= TokenPosition::build(&node_position,&position);
-            //     token_position_list.push(token_position);
-            // }
-        }else if first_name =="##" {
-            let last_text = &template[first_start..];
-            // log::debug!("start:{:?}   last_text:{:?}",node_position,last_text);
-            let mut last_start = first_start+find_char_index(last_text,"\r\n").unwrap();
-            let  last_end = last_start+2 ;
-
-            let position = NodePosition::new("##", last_start, last_end);
-            // log::debug!("set----start:{:?}   end:{:?}", node_position,position);
-            let token_position = TokenPosition::build(&node_position, &position);
-            token_position_list.push(token_position);
-        //
-        // }else if first_name =="#*" {
-        //     let last_text = &template[first_start..];
-        //     let mut last_start = find_char_index(last_text,r#"*#"#).unwrap();
-        //     let  last_end = last_start + 2;
-        //
-        //     let position = NodePosition::new("*#", last_start, last_end);
-        //     log::debug!("set----start:{:?}   end:{:?}", node_position,position);
-        //     let token_position = TokenPosition::build(&node_position, &position);
-        //     token_position_list.push(token_position);
-        }else {
-            stack.push(node_position);
+impl<'a> RenderState<'a> {
+    pub fn new(engine: &'a VelocityEngine, name: &str, source: Arc<str>) -> Self {
+        RenderState {
+            engine,
+            config: engine.config(),
+            name: name.to_string(),
+            source,
+            macros: HashMap::new(),
+            macro_depth: 0,
+            parse_stack: vec![name.to_string()],
+            escape: engine.config().escape,
+            foreach_depth: 0,
+            interrupt: None,
         }
     }
 
-    // log::debug!("token_position_list: {:#?}", token_position_list);
-
-    let mut read_index = 0;
-    let read_end = template.len();
-    let mut  position_list =Vec::new();
-    // let mut position_list_temp:Vec<TokenPosition> = Vec::new();
-
-    for position in &token_position_list {
-        let first_start = position.first_start;
-        let first_end = position.first_end;
-        let last_start = position.last_start;
-        let last_end = position.last_end;
-        let first_name = &position.first_name;
-        let last_name = &position.last_name;
-
-        // log::debug!("---------- position first_name:{} last_name:{} first_start:{} last_end:{}",first_name,last_name,first_start,last_end);
-
-        if position.is_root(&token_position_list) {
-            if read_index < first_start  {
-                position_list.push(TokenPosition::new_text(read_index,first_start));
-            }
-            position_list.push(position.clone());
-            read_index = last_end;
-        }
+    pub fn location(&self, offset: usize) -> Box<Location> {
+        Box::new(Location::new(&self.name, &self.source, offset))
     }
 
-    if read_index<read_end{
-
-        let text_position = TokenPosition::new_text(read_index+read_start_index,read_end+read_start_index);
-        // log::debug!("---------- text_position:{:?}",text_position);
-        position_list.push(text_position);
+    /// 使用引擎事件处理器的表达式计算
+    pub fn evaluator<'b>(&'b self, context: &'b Context, strict: bool) -> Evaluator<'b> {
+        Evaluator::new(context, strict)
+            .with_events(&self.config.events)
+            .with_max_range_length(self.config.max_range_length)
     }
 
-    // log::debug!("position_list: {:#?}", position_list);
-    Ok(position_list)
-}
-
-pub fn position_to_tokenizer(template:&str,position_list:& [TokenPosition])-> Result<Vec<Tokenizer>,String>{
-
-    let mut tokens:Vec<Tokenizer> = Vec::new();
-    let mut position_list_temp:Vec<TokenPosition> = Vec::new();
-
-    for position in position_list {
-        let first_name = &position.first_name;
-        let last_name = &position.last_name;
-        let first_start = position.first_start;
-        let first_end = position.first_end;
-        let last_start = position.last_start;
-        let last_end = position.last_end;
-
-        // log::debug!(" first_name:{} last_name:{}  first_start:{} first_end:{} last_start:{} last_end:{}",first_name,last_name,first_start,first_end,last_start,last_end);
-
-        if first_name == "#text" || first_name =="<!--" {
-
-            // log::debug!("text--first_start:{:?}   last_end:{:?} first_name:{:?} last_name:{:?}",first_start,last_start,first_name,last_name);
-            let text =&template[first_start..last_end];
-            let text_token = Tokenizer::new_text(text);
-            tokens.push(text_token);
-
-        }else if first_name == "#set" {
-
-            let set_text = &template[first_end + 1..last_end - 1];
-            if !set_text.is_empty() {
-                if let Some((key, value)) = set_text.split_once('=') {
-                    let text = Tokenizer::new_set(key.trim(), value.trim());
-                    tokens.push(text);
-                } else {
-                    return Err("#set Syntax error".to_string())
-                }
-            }
-        }else if first_name == "#if" {
-
-            let mut if_tokens = Vec::new();
+    /// 计算指令中的表达式，offset 为表达式在模板中的位置
+    pub fn expression_evaluator<'b>(&'b self, context: &'b Context, offset: usize) -> Evaluator<'b> {
+        self.evaluator(context, self.config.strict).at(&self.name, &self.source, offset)
+    }
 
-            let mut if_last_name = last_name.clone();
-            let mut if_last_start = last_start;
-            let mut temp = position.clone();
-            loop {
+    /// 表达式错误转换为 VelocityError，start 为表达式在模板中的位置
+    pub fn expression_error(&self, error: ExpressionError, start: usize) -> VelocityError {
+        let location = self.location(start + error.offset);
+        match error.undefined {
+            Some(name) => VelocityError::UndefinedReference { name, location },
+            None => VelocityError::Expression {
+                message: error.message,
+                location: Some(location),
+            },
+        }
+    }
 
-                let if_token_result =  parse_if(template,&temp);
-                if if_token_result.is_err() {
-                    return Err(if_token_result.unwrap_err());
+    /// 注册模板中定义的所有宏，宏可以在定义之前调用
+    pub fn define_macros(&mut self, tokens: &[Tokenizer]) {
+        for token in tokens {
+            match token {
+                Tokenizer::Macro { definition, .. } => {
+                    self.macros.insert(definition.name.clone(), definition.clone());
+                    self.define_macros(&definition.children);
                 }
-                let if_branch = if_token_result.unwrap();
-                if_tokens.push(if_branch);
-
-                if last_name == "#end" {
-                    break;
+                Tokenizer::If { branches, .. } => {
+                    for branch in branches {
+                        self.define_macros(&branch.children);
+                    }
                 }
-
-                let else_position =position_list.iter()
-                    .find(|t|  if_last_name == t.first_name && if_last_start == t.first_start)
-                    .cloned();
-                if let Some(token_position) = else_position {
-                    if_last_name = token_position.last_name.clone();
-                    if_last_start = token_position.last_start;
-                    temp = token_position.clone();
-                }else{
-                    break;
+                Tokenizer::Foreach { children, else_children, .. } => {
+                    self.define_macros(children);
+                    if let Some(else_children) = else_children {
+                        self.define_macros(else_children);
+                    }
                 }
-
-            }
-
-            tokens.push(Tokenizer::new_if(if_tokens));
-        }else if first_name=="#foreach" {
-            let foreach_all_text = &template[first_start..last_end];
-
-            let mut expression_start = 0;
-            let mut expression_end = 0;
-            let bracket_range = find_tag_bracket_range(foreach_all_text,first_name);
-            if let Some((start,end)) = bracket_range {
-                expression_start = first_start+start;
-                expression_end   = first_start+end;
-            }else{
-                return Err(format!("Error: No valid end found for the expression following the tag '{}' in the input string.",first_name))
-            }
-            let foreach_expression = &template[expression_start + 1..expression_end];
-            let foreach_child_text = &template[expression_end + 1..last_start];
-            if foreach_child_text.is_empty() {
-                continue;
-            }
-
-            let mut token_position_list = Vec::new();
-            let token_position_result = parse_position(&foreach_child_text,0);
-            if let Ok(token_position) = token_position_result {
-                token_position_list.extend(token_position);
+                Tokenizer::MacroCall { body: Some(children), .. } => self.define_macros(children),
+                _ => {}
             }
-            let children_tokens_result = position_to_tokenizer(&foreach_child_text, &mut token_position_list);
-            if children_tokens_result.is_err() {
-                return children_tokens_result;
-            }
-            let children_tokens = children_tokens_result.unwrap();
-            if children_tokens.is_empty() {
-                continue;
-            }
-
-            // 按照 "in" 分割
-            let parts: Vec<&str> = foreach_expression.split("in").map(str::trim).collect();
-            if parts.len() == 2 {
-                let variable = parts[0].trim();
-                let collection = parts[1].trim();
-                // println!("Variable: {}", variable);
-                // println!("Collection: {}", collection);
-                let foreach_token = Tokenizer::new_foreach(variable, collection, children_tokens);
-                tokens.push(foreach_token);
-            } else {
-                return Err("#foreach Syntax error".to_string())
-            }
-
-
         }
-        position_list_temp.push(position.clone());
     }
-
-    // log::debug!("tokens--------------------------\n{:#?}",tokens);
-
-    Ok(tokens)
 }
 
 
-pub fn parse_if(template:&str, position:&TokenPosition) -> Result<IfBranch,String> {
-
-    let first_name = &position.first_name;
-    let last_name = &position.last_name;
-    let first_start = position.first_start;
-    let first_end = position.first_end;
-    let last_start = position.last_start;
-    let last_end = position.last_end;
-    // log::debug!("template:{:?} first_end：{} last_start：{}",template,first_end,last_start);
-
-    let child_text = &template[first_end..last_start];
-    // log::debug!("child_text:{:?}",child_text);
-
-    if first_name =="#else" {
-
-        let mut token_position_list = Vec::new();
-        let token_position_result = parse_position(&child_text,0);
-        if let Ok(token_position) = token_position_result {
-            token_position_list.extend(token_position);
-        }
-
-        let children_tokens_result = position_to_tokenizer(child_text, &mut token_position_list);
-        if children_tokens_result.is_err() {
-            return Err(children_tokens_result.unwrap_err());
-        }
-        let children_tokens = children_tokens_result.unwrap();
-
-        // log::debug!("children tokens:{:?}",children_tokens);
-        let if_token = IfBranch::new("true".to_string(),children_tokens);
-        return Ok(if_token);
-
-    }else if first_name=="#if" || first_name=="#elseif" {
-
-
-        let text = &template[first_start..last_start];
-        let mut expression_start = 0;
-        let mut expression_end = 0;
-        let bracket_range = find_tag_bracket_range(text,first_name);
-        if let Some((start,end)) = bracket_range {
-            expression_start = first_start+start;
-            expression_end   = first_start+end;
-        }else{
-            return Err(format!("Error: No valid end found for the expression following the tag '{}' in the input string.",first_name))
-        }
-
-        // log::debug!("text:{:?} expression_start:{} expression_end:{}",text,expression_start,expression_end);
-        let condition = &template[expression_start+1..expression_end].trim();
-        let child_text = &template[expression_end+1..last_start];
-
-
-        let mut token_position_list = Vec::new();
-        let token_position_result = parse_position(&child_text,0);
-        if let Ok(token_position) = token_position_result {
-            token_position_list.extend(token_position);
-        }
+pub fn get_tokens(template:&str) -> Result<Vec<Tokenizer>, VelocityError> {
+    get_named_tokens(INLINE_TEMPLATE_NAME, template, &RenderConfig::default())
+}
 
-        let children_tokens_result = position_to_tokenizer(child_text, &mut token_position_list);
-        if children_tokens_result.is_err() {
-            return Err(children_tokens_result.unwrap_err());
-        }
-        let children_tokens = children_tokens_result.unwrap();
-        // log::debug!("children_tokens:{:#?}",children_tokens);
+/// 与 get_tokens 相同，错误信息中使用给定的模板名称（通常是文件路径）。解析结果由 VelocityEngine 缓存
+pub fn get_named_tokens(name: &str, template: &str, config: &RenderConfig) -> Result<Vec<Tokenizer>, VelocityError> {
+    let source = TemplateSource::new(name, template);
+    parser::parse(&source, config.space_gobbling)
+}
 
-        let if_token = IfBranch::new(condition.to_string(),children_tokens);
-        return Ok(if_token);
-    }
 
-    Err(format!("Unknown tag:{}",first_name))
+/// 写入渲染结果，写入失败时返回 `VelocityError::Write`
+pub(crate) fn write(out: &mut dyn fmt::Write, text: &str) -> Result<(), VelocityError> {
+    out.write_str(text).map_err(|_| VelocityError::Write {
+        source: std::io::Error::other("failed to write template output"),
+    })
 }
 
 
-pub fn parse_tokens(tokens:&[Tokenizer], content: &mut HashMap<String, Value>) -> Option<String> {
-    if tokens.is_empty() {
-        return None;
-    }
-
-    let mut output = String::new();
+pub fn parse_tokens(tokens:&[Tokenizer], content: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError> {
     for token in tokens {
-        let v = parse_token(token,content);
-        if let Some(v) = v {
-            output.push_str(&v);
+        parse_token(token, content, state, out)?;
+        if state.interrupt.is_some() {
+            break;
         }
     }
-
-    Some(output)
+    Ok(())
 }
 
 
-pub fn parse_token(token:&Tokenizer,content: &mut HashMap<String, Value>) -> Option<String>{
+pub fn parse_token(token:&Tokenizer,content: &mut Context, state: &mut RenderState, out: &mut dyn fmt::Write) -> Result<(), VelocityError>{
     match token {
         Tokenizer::Text { .. } => {
-            text_parse::text_parse(&token, content)
+            text_parse::text_parse(token, out)
+        }
+        Tokenizer::Reference { .. } => {
+            variable_parse::reference_parse(token, content, state, out)
+        }
+        Tokenizer::EscapedReference { .. } => {
+            variable_parse::escaped_reference_parse(token, content, state, out)
         }
         Tokenizer::Set { .. } => {
-            set_parse::set_parse(token,content);
-            None
+            set_parse::set_parse(token,content,state)
         }
         Tokenizer::If { ..} => {
-            if_parse::if_parse(token,content)
+            if_parse::if_parse(token,content,state,out)
         }
         Tokenizer::Foreach { .. } => {
-            foreach_parse::foreach_parse(token,content)
+            foreach_parse::foreach_parse(token,content,state,out)
         }
-    }
-}
-
-pub fn find_tag_bracket_range(input: &str, tag: &str) -> Option<(usize, usize)> {
-    let start_index = match input.find(tag) {
-        Some(index) => index,
-        None => return None,
-    };
-
-    let mut stack = 0;
-    let mut first_open_paren_index = None;
-
-    for (i, c) in input[start_index..].char_indices() {
-        match c {
-            '(' => {
-                if first_open_paren_index.is_none() {
-                    first_open_paren_index = Some(start_index + i);
-                }
-                stack += 1;
-            }
-            ')' => {
-                stack -= 1;
-                if stack == 0 {
-                    return Some((first_open_paren_index?, start_index + i));
-                }
-            }
-            _ => {}
+        // 宏在渲染开始前已经注册
+        Tokenizer::Macro { .. } => {
+            Ok(())
         }
-    }
-    None
-}
-
-
-
-fn find_tag_end(input: &str, tag: &str) -> Option<usize> {
-    let start_index = match input.find(tag) {
-        Some(index) => index,
-        None => return None,
-    };
-    let mut stack = 0;
-    for (i, c) in input[start_index..].char_indices() {
-        match c {
-            '(' => stack += 1,
-            ')' => {
-                stack -= 1;
-                if stack == 0 {
-                    return Some(start_index + i);
-                }
-            }
-            _ => {}
+        Tokenizer::MacroCall { .. } => {
+            macro_parse::macro_call_parse(token,content,state,out)
+        }
+        Tokenizer::Parse { .. } => {
+            include_parse::template_parse(token,content,state,out)
+        }
+        Tokenizer::Include { .. } => {
+            include_parse::include_parse(token,content,state,out)
+        }
+        // 不在 #foreach 中的 #break($foreach) 不起作用
+        Tokenizer::Break { scope: BreakScope::Foreach, .. } if state.foreach_depth == 0 => {
+            Ok(())
+        }
+        Tokenizer::Break { scope, .. } => {
+            state.interrupt = Some(Interrupt::Break(*scope));
+            Ok(())
+        }
+        Tokenizer::Stop { .. } => {
+            state.interrupt = Some(Interrupt::Stop);
+            Ok(())
         }
     }
-    None
-}
-
-fn find_char_index(input: &str, target: &str) -> Option<usize> {
-    if target.is_empty() {
-        return None;
-    }
-    let re = Regex::new(format!(r"{}",target).as_str()).unwrap();
-    if let Some(capture) = re.find(input) {
-        return Some(capture.start());
-    }
-    None
 }
-
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{Escape, MemoryResourceLoader, VelocityEngine};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "text": "<a href=\"x\">Tom & 'Jerry'</a>",
+        "script": "it's \"done\"\n</script>\\",
+        "user": {"name": "O'Brien", "age": 17},
+        "payload": "\\' OR 1=1 -- "
+    }))
+}
+
+fn render(escape: Escape, template: &str) -> String {
+    let engine = VelocityEngine::builder().escape(escape).build();
+    engine.render(template, &mut context()).unwrap()
+}
+
+#[test]
+pub fn html_test() {
+    assert_eq!(render(Escape::Html, "<p>$text</p>"), "<p>&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;</p>");
+    assert_eq!(render(Escape::Xml, "$!{user.name} $user.age"), "O&apos;Brien 17");
+    assert_eq!(render(Escape::Html, "$missing"), "${missing}");
+}
+
+#[test]
+pub fn script_test() {
+    assert_eq!(render(Escape::Json, "\"$script\""), "\"it's \\\"done\\\"\\n</script>\\\\\"");
+    assert_eq!(render(Escape::JavaScript, "'$script'"), "'it\\'s \\\"done\\\"\\n<\\/script>\\\\'");
+    assert_eq!(render(Escape::Json, "$user"), "{\\\"age\\\":17,\\\"name\\\":\\\"O'Brien\\\"}");
+}
+
+#[test]
+pub fn sql_test() {
+    assert_eq!(render(Escape::Sql, "WHERE name = '$user.name'"), "WHERE name = 'O''Brien'");
+    // 反斜杠也要转义，否则 `\'` 在 MySQL 中是转义的单引号，后面的单引号会结束字符串
+    assert_eq!(render(Escape::Sql, "WHERE name = '$payload'"), "WHERE name = '\\\\'' OR 1=1 -- '");
+    assert_eq!(render(Escape::None, "$user.name"), "O'Brien");
+}
+
+#[test]
+pub fn safe_test() {
+    assert_eq!(render(Escape::Html, "$text.safe()|$!text.safe()"), "<a href=\"x\">Tom & 'Jerry'</a>|<a href=\"x\">Tom & 'Jerry'</a>");
+    assert_eq!(render(Escape::Html, "$text.substring(0, 2).safe()"), "<a");
+    // 已转义的标记随 #set 和宏参数传递，重新赋值后取消
+    assert_eq!(render(Escape::Sql, "#set($name = $user.name.safe())$name #set($copy = $name)$copy"), "O'Brien O'Brien");
+    assert_eq!(render(Escape::Sql, "#set($name = $user.name.safe())#set($name = $user.name)$name"), "O''Brien");
+    assert_eq!(render(Escape::Html, "#macro(show $v)$v#end#show($text.safe()) #show($user.name)"), "<a href=\"x\">Tom & 'Jerry'</a> O&#39;Brien");
+}
+
+#[test]
+pub fn body_content_test() {
+    // $bodyContent 中的引用已经在调用处转义过，不再转义
+    let mut context = context();
+    context.insert("x".to_string(), json!("<i>"));
+    let engine = VelocityEngine::builder().escape(Escape::Html).build();
+    let output = engine.render("#macro(box)<div>$!bodyContent</div>#end#@box()<b>$x</b>#end", &mut context).unwrap();
+    assert_eq!(output, "<div><b>&lt;i&gt;</b></div>");
+}
+
+#[test]
+pub fn template_test() {
+    let loader = MemoryResourceLoader::new();
+    loader.insert("page.vm", "<b>$user.name</b>");
+    loader.insert("raw.txt", "<i>$user.name</i>");
+    let engine = VelocityEngine::builder().loader(loader).escape(Escape::Html).build();
+
+    let template = engine.compile("$user.name #parse('page.vm') #include('raw.txt')").unwrap();
+    assert_eq!(template.escape(), Escape::Html);
+    assert_eq!(template.render(&context()).unwrap(), "O&#39;Brien <b>O&#39;Brien</b> <i>$user.name</i>");
+
+    let sql = template.with_escape(Escape::Sql);
+    assert_eq!(sql.render(&context()).unwrap(), "O''Brien <b>O''Brien</b> <i>$user.name</i>");
+    assert_eq!(template.render(&context()).unwrap(), "O&#39;Brien <b>O&#39;Brien</b> <i>$user.name</i>");
+}
+
+#[test]
+pub fn from_str_test() {
+    assert_eq!("html".parse::<Escape>().unwrap(), Escape::Html);
+    assert_eq!("XML".parse::<Escape>().unwrap(), Escape::Xml);
+    assert_eq!("json".parse::<Escape>().unwrap(), Escape::Json);
+    assert_eq!("javascript".parse::<Escape>().unwrap(), Escape::JavaScript);
+    assert_eq!("sql".parse::<Escape>().unwrap(), Escape::Sql);
+    assert_eq!("none".parse::<Escape>().unwrap(), Escape::None);
+    assert!("url".parse::<Escape>().is_err());
+}
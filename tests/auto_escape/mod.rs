@@ -0,0 +1 @@
+pub mod auto_escape_test;
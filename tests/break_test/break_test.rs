@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{render, MemoryResourceLoader, VelocityEngine, VelocityError};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "list": [1, 2, 3, 4]
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn break_foreach_test() {
+    assert_eq!(eval("#foreach($i in $list)#if($i == 3)#break#end$i #{end}done"), "1 2 done");
+    assert_eq!(eval("#foreach($i in $list)$i#break($foreach) never#{end}|"), "1|");
+    // 只结束最内层的循环
+    let template = "#foreach($a in $list)#foreach($b in $list)#if($b > $a)#break#end$b#{end},#{end}";
+    assert_eq!(eval(template), "1,12,123,1234,");
+    assert_eq!(eval("#foreach($i in $list)#if($i == 2)#break#{end}$i#{end}[$!foreach]"), "1[]");
+}
+
+#[test]
+pub fn break_template_test() {
+    assert_eq!(eval("before #break after"), "before ");
+    assert_eq!(eval("before #foreach($i in $list)$i#break($parse)#{end} after"), "before 1");
+    // 不在循环中的 #break($foreach) 不起作用
+    assert_eq!(eval("a#break($foreach)b"), "ab");
+}
+
+#[test]
+pub fn break_macro_test() {
+    // 只结束宏的内容，不影响调用处
+    assert_eq!(eval("#macro(m)a#break b#end#m() after"), "a after");
+    assert_eq!(eval("#macro(check $i)#if($i > 1)#break#end$i#end#foreach($i in $list)#check($i),#{end}!"), "1,,,,!");
+    // 只结束块宏调用处的内容
+    assert_eq!(eval("#@m()x#break y#end after#macro(m)[$bodyContent]#end"), "[x] after");
+    // #break($foreach) 仍然结束宏所在的循环
+    assert_eq!(eval("#macro(m)[$bodyContent]#end#foreach($i in $list)#@m()$i#if($i == 2)#break($foreach)#end#end#{end}."), "[1].");
+}
+
+#[test]
+pub fn break_parse_test() {
+    let loader = MemoryResourceLoader::new();
+    loader.insert("child.vm", "child #break($parse) hidden");
+    loader.insert("loop.vm", "#foreach($i in $list)$i#if($i == 2)#break#end#{end}.");
+    loader.insert("stop.vm", "stop #stop hidden");
+    let engine = VelocityEngine::builder().loader(loader).build();
+
+    let output = engine.render("[#parse('child.vm')] [#parse('loop.vm')] end", &mut context()).unwrap();
+    assert_eq!(output, "[child ] [12.] end");
+    let output = engine.render("#foreach($i in $list)#parse('child.vm')#{end}", &mut context()).unwrap();
+    assert_eq!(output, "child child child child ");
+    let output = engine.render("#foreach($i in $list)$i#parse('stop.vm')#{end} end", &mut context()).unwrap();
+    assert_eq!(output, "1stop ");
+}
+
+#[test]
+pub fn stop_test() {
+    assert_eq!(eval("a #stop b"), "a ");
+    assert_eq!(eval("#foreach($i in $list)#foreach($j in $list)$j#if($j == 2)#stop#end#{end}#{end} end"), "12");
+    let mut context = context();
+    assert_eq!(render("#set($a = 1)#stop#set($b = 2)", &mut context).unwrap(), "");
+    assert!(context.contains_key("a") && !context.contains_key("b"));
+}
+
+#[test]
+pub fn invalid_break_test() {
+    let error = render("#break($macro)", &mut context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::Syntax { message, .. } if message == "expected $foreach or $parse in #break"), "{:?}", error);
+    assert_eq!(eval("#break (text)"), "");
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use serde_json::Value;
+use velocity_template::{CachePolicy, CacheStats, FileResourceLoader, MemoryResourceLoader, VelocityEngine};
+
+fn stats(hits: u64, misses: u64, entries: usize) -> CacheStats {
+    CacheStats { hits, misses, entries }
+}
+
+#[test]
+pub fn cache_stats_test() {
+    let engine = VelocityEngine::default();
+    assert_eq!(engine.cache_stats(), stats(0, 0, 0));
+
+    for _ in 0..3 {
+        engine.render("a", &mut HashMap::new()).unwrap();
+    }
+    engine.compile("b").unwrap();
+    assert_eq!(engine.cache_stats(), stats(2, 2, 2));
+
+    // 克隆的引擎共享缓存
+    engine.clone().compile("b").unwrap();
+    assert_eq!(engine.cache_stats(), stats(3, 2, 2));
+
+    engine.clear_cache();
+    assert_eq!(engine.cache_stats(), stats(3, 2, 0));
+}
+
+#[test]
+pub fn lru_test() {
+    let engine = VelocityEngine::builder().cache_policy(CachePolicy::Lru(2)).build();
+    engine.compile("a").unwrap();
+    engine.compile("b").unwrap();
+    engine.compile("a").unwrap();
+    // 淘汰最久未使用的 b
+    engine.compile("c").unwrap();
+    assert_eq!(engine.cache_stats(), stats(1, 3, 2));
+
+    engine.compile("a").unwrap();
+    assert_eq!(engine.cache_stats(), stats(2, 3, 2));
+    engine.compile("b").unwrap();
+    assert_eq!(engine.cache_stats(), stats(2, 4, 2));
+}
+
+#[test]
+pub fn disabled_test() {
+    for policy in [CachePolicy::Disabled, CachePolicy::Lru(0)] {
+        let engine = VelocityEngine::builder().cache_policy(policy).build();
+        engine.compile("a").unwrap();
+        engine.compile("a").unwrap();
+        assert_eq!(engine.cache_stats(), stats(0, 2, 0));
+    }
+
+    let engine = VelocityEngine::builder().cache_policy(CachePolicy::Unbounded).build();
+    for i in 0..1000 {
+        engine.compile(&i.to_string()).unwrap();
+    }
+    assert_eq!(engine.cache_stats().entries, 1000);
+}
+
+#[test]
+pub fn memory_loader_invalidation_test() {
+    let loader = Arc::new(MemoryResourceLoader::new());
+    loader.insert("page.vm", "v1");
+    let engine = VelocityEngine::builder().loader(loader.clone()).build();
+    let mut context: HashMap<String, Value> = HashMap::new();
+
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v1");
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v1");
+    assert_eq!(engine.cache_stats(), stats(1, 1, 1));
+
+    // 修改时间变化后重新加载
+    loader.insert("page.vm", "v2");
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v2");
+    assert_eq!(engine.cache_stats(), stats(1, 2, 1));
+
+    // #parse 同样会检查修改时间
+    loader.insert("layout.vm", "[#parse(\"page.vm\")]");
+    assert_eq!(engine.render_template("layout.vm", &mut context).unwrap(), "[v2]");
+    loader.insert("page.vm", "v3");
+    assert_eq!(engine.render_template("layout.vm", &mut context).unwrap(), "[v3]");
+}
+
+#[test]
+pub fn file_invalidation_test() {
+    let dir = std::env::temp_dir().join(format!("velocity_cache_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("page.vm");
+    fs::write(&path, "v1").unwrap();
+
+    let engine = VelocityEngine::builder().loader(FileResourceLoader::new(&dir)).build();
+    let mut context: HashMap<String, Value> = HashMap::new();
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v1");
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v1");
+
+    fs::write(&path, "v2").unwrap();
+    File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(engine.render_template("page.vm", &mut context).unwrap(), "v2");
+    assert_eq!(engine.cache_stats(), stats(1, 2, 1));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use velocity_template::render;
+
+/// 把 `json!` 写的对象转换为渲染用的上下文
+pub fn context(value: Value) -> HashMap<String, Value> {
+    match value {
+        Value::Object(map) => map.into_iter().collect(),
+        other => panic!("context must be an object, found {}", other),
+    }
+}
+
+/// 使用默认配置渲染模板，渲染失败时测试失败
+pub fn eval(template: &str, mut context: HashMap<String, Value>) -> String {
+    render(template, &mut context).unwrap()
+}
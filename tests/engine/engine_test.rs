@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::thread;
+use serde_json::{json, Value};
+use velocity_template::{render, CachePolicy, MemoryResourceLoader, SpaceGobbling, VelocityEngine};
+use crate::common;
+
+const TEMPLATE: &str = "#if(true)\n  $name\n#end\n";
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "Tom"
+    }))
+}
+
+#[test]
+pub fn isolated_engines_test() {
+    // 同一进程中不同配置的引擎互不影响
+    let none = VelocityEngine::builder().space_gobbling(SpaceGobbling::None).build();
+    let structured = VelocityEngine::builder().space_gobbling(SpaceGobbling::Structured).build();
+
+    assert_eq!(none.render(TEMPLATE, &mut context()).unwrap(), "\n  Tom\n\n");
+    assert_eq!(structured.render(TEMPLATE, &mut context()).unwrap(), "Tom\n");
+    assert_eq!(VelocityEngine::default().render(TEMPLATE, &mut context()).unwrap(), "  Tom\n");
+    assert_eq!(render(TEMPLATE, &mut context()).unwrap(), "  Tom\n");
+}
+
+#[test]
+pub fn tool_test() {
+    let engine = VelocityEngine::builder()
+        .tool("app", json!({"name": "demo", "version": 2}))
+        .tool("name", json!("tool"))
+        .build();
+
+    // 工具变量不覆盖用户变量，渲染结束后不会留在上下文中
+    let mut context = context();
+    assert_eq!(engine.render("${app.name} v${app.version} $name", &mut context).unwrap(), "demo v2 Tom");
+    assert_eq!(context.len(), 1);
+
+    assert_eq!(engine.render("$name", &mut HashMap::new()).unwrap(), "tool");
+}
+
+#[test]
+pub fn loader_chain_test() {
+    let first = MemoryResourceLoader::new();
+    first.insert("page.vm", "first #parse(\"footer.vm\")");
+    let second = MemoryResourceLoader::new();
+    second.insert("page.vm", "second");
+    second.insert("footer.vm", "footer $name");
+
+    let engine = VelocityEngine::builder().loader(first).loader(second).build();
+    assert_eq!(engine.render_template("page.vm", &mut context()).unwrap(), "first footer Tom");
+    assert!(engine.render_template("missing.vm", &mut context()).is_err());
+}
+
+#[test]
+pub fn cache_policy_test() {
+    for policy in [CachePolicy::Disabled, CachePolicy::Unbounded] {
+        let engine = VelocityEngine::builder().cache_policy(policy).build();
+        for _ in 0..3 {
+            assert_eq!(engine.render("$name!", &mut context()).unwrap(), "Tom!");
+        }
+    }
+}
+
+#[test]
+pub fn shared_engine_test() {
+    // 克隆的引擎共享配置和缓存，可以在多个线程中使用
+    let engine = VelocityEngine::builder().tool("suffix", json!("!")).build();
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let engine = engine.clone();
+            thread::spawn(move || {
+                let mut context: HashMap<String, Value> = HashMap::new();
+                context.insert("i".to_string(), json!(i));
+                engine.render("$i$suffix", &mut context).unwrap()
+            })
+        })
+        .collect();
+    let outputs: Vec<String> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(outputs, vec!["0!", "1!", "2!", "3!"]);
+}
@@ -0,0 +1,57 @@
+use velocity_template::{render_default, render_default_path, VelocityError};
+
+#[test]
+pub fn unmatched_end_test() {
+    let template = "line 1\nline 2 #end\n";
+    let error = render_default(template).unwrap_err();
+
+    match &error {
+        VelocityError::UnmatchedBlock { directive, location } => {
+            assert_eq!(directive, "#end");
+            assert_eq!(location.offset, 14);
+            assert_eq!(location.line, 2);
+            assert_eq!(location.column, 8);
+            assert_eq!(location.snippet, "line 2 #end\n       ^");
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+    println!("{}", error);
+}
+
+#[test]
+pub fn unclosed_block_test() {
+    let template = "#if(true)\n  1\n#foreach($item in $list)\n";
+    let error = render_default(template).unwrap_err();
+
+    match &error {
+        VelocityError::UnmatchedBlock { directive, location } => {
+            // 最内层没有结束的块
+            assert_eq!(directive, "#foreach");
+            assert_eq!(location.line, 3);
+            assert_eq!(location.column, 1);
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+pub fn location_after_comment_test() {
+    // 注释在解析前被移除，错误位置仍然需要指向原始模板
+    let template = "## comment\n#* a\n b *#\n  #foreach($item)\n#end";
+    let error = render_default(template).unwrap_err();
+
+    match &error {
+        VelocityError::InvalidForeach { location, .. } => {
+            assert_eq!(location.line, 4);
+            assert_eq!(location.column, 12);
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+pub fn io_error_test() {
+    let error = render_default_path("tests/error/not_found.vm").unwrap_err();
+    assert!(matches!(error, VelocityError::Io { .. }));
+    assert!(error.location().is_none());
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::render;
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "Tom",
+        "flag": true
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn reference_test() {
+    assert_eq!(eval("\\$name"), "$name");
+    assert_eq!(eval("\\\\$name"), "\\Tom");
+    assert_eq!(eval("\\\\\\$name"), "\\$name");
+    assert_eq!(eval("\\$!{name}|\\$name.length()"), "$!{name}|$name.length()");
+    // 与 Velocity 相同，引用没有值时 `\` 原样输出
+    assert_eq!(eval("\\$missing"), "\\$missing");
+    assert_eq!(eval("\\\\\\$missing.name|\\$!{missing}"), "\\\\\\$missing.name|\\$!{missing}");
+}
+
+#[test]
+pub fn directive_test() {
+    assert_eq!(eval("\\#if($flag)yes\\#{else}no\\#end"), "#if(true)yes#{else}no#end");
+    assert_eq!(eval("\\\\#if($flag)yes#{end}"), "\\yes");
+    assert_eq!(eval("\\#set($name = 'Jerry')$name"), "#set(Tom = 'Jerry')Tom");
+    assert_eq!(eval("\\#foo(1)"), "#foo(1)");
+}
+
+#[test]
+pub fn literal_backslash_test() {
+    assert_eq!(eval("C:\\temp\\file"), "C:\\temp\\file");
+    assert_eq!(eval("\\\\n \\#hash \\$ 5"), "\\\\n \\#hash \\$ 5");
+}
+
+#[test]
+pub fn unparsed_block_test() {
+    assert_eq!(eval("#[[#if($flag) $name #end]]#|$name"), "#if($flag) $name #end|Tom");
+    assert_eq!(eval("#[[## not a comment]]#"), "## not a comment");
+    assert!(render("#[[ $name", &mut context()).is_err());
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{
+    IncludeHandler, InvalidReferenceHandler, Location, MemoryResourceLoader, MethodExceptionHandler,
+    ReferenceInsertionHandler, VelocityEngine, VelocityEngineBuilder, VelocityError,
+};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "<b>Tom</b>",
+        "age": 17,
+        "nothing": null
+    }))
+}
+
+fn render(builder: VelocityEngineBuilder, template: &str) -> Result<String, VelocityError> {
+    builder.build().render(template, &mut context())
+}
+
+#[derive(Debug)]
+struct HtmlEscape;
+
+impl ReferenceInsertionHandler for HtmlEscape {
+    fn reference_insert(&self, _reference: &str, value: Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(s.replace('<', "&lt;").replace('>', "&gt;")),
+            value => value,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Brackets;
+
+impl ReferenceInsertionHandler for Brackets {
+    fn reference_insert(&self, reference: &str, value: Value) -> Value {
+        Value::String(format!("[{}={}]", reference, value.as_str().map(String::from).unwrap_or(value.to_string())))
+    }
+}
+
+#[derive(Debug)]
+struct Placeholder;
+
+impl InvalidReferenceHandler for Placeholder {
+    fn invalid_reference(&self, reference: &str, location: &Location) -> Option<Value> {
+        (reference != "keep").then(|| Value::String(format!("<{}@{}>", reference, location.column)))
+    }
+}
+
+#[derive(Debug)]
+struct Includes;
+
+impl IncludeHandler for Includes {
+    fn include(&self, path: &str, current: &str, directive: &str) -> Option<String> {
+        if path.starts_with("secret") {
+            return None;
+        }
+        Some(format!("{}/{}", directive, path.trim_start_matches(current)))
+    }
+}
+
+#[derive(Debug)]
+struct MethodFallback;
+
+impl MethodExceptionHandler for MethodFallback {
+    fn method_exception(&self, value: &Value, method: &str, message: &str) -> Option<Value> {
+        (method == "substring").then(|| Value::String(format!("{}:{}", value.as_str().unwrap_or_default(), message)))
+    }
+}
+
+#[test]
+pub fn reference_insertion_test() {
+    let builder = VelocityEngine::builder().reference_insertion_handler(HtmlEscape);
+    assert_eq!(render(builder, "$name $!{name} $age #set($x = $name)$x").unwrap(), "&lt;b&gt;Tom&lt;/b&gt; &lt;b&gt;Tom&lt;/b&gt; 17 &lt;b&gt;Tom&lt;/b&gt;");
+
+    // 按注册顺序依次转换，未定义的引用不经过
+    let builder = VelocityEngine::builder().reference_insertion_handler(HtmlEscape).reference_insertion_handler(Brackets);
+    assert_eq!(render(builder, "$name $age $missing").unwrap(), "[name=&lt;b&gt;Tom&lt;/b&gt;] [age=17] ${missing}");
+}
+
+#[test]
+pub fn invalid_reference_test() {
+    let builder = || VelocityEngine::builder().invalid_reference_handler(Placeholder);
+    assert_eq!(render(builder(), "$missing $nothing $keep [$!quiet]").unwrap(), "<missing@1> <nothing@10> ${keep} []");
+    assert_eq!(render(builder().reference_insertion_handler(Brackets), "$a.b").unwrap(), "[a.b=<a.b@1>]");
+
+    // 严格模式下处理器给出值时不报错
+    assert_eq!(render(builder().strict(true), "$missing").unwrap(), "<missing@1>");
+    assert!(render(builder().strict(true), "$keep").is_err());
+
+    // 指令中的引用也交给处理器，位置为表达式的开头
+    assert_eq!(render(builder(), "#if($missing)yes#{else}no#end").unwrap(), "yes");
+    assert_eq!(render(builder(), "#if($keep)yes#{else}no#end").unwrap(), "no");
+    assert_eq!(render(builder(), "#set($x = $missing)$x").unwrap(), "<missing@11>");
+    assert_eq!(render(builder(), "#set($x = \"[$a.b]\")$x").unwrap(), "[<a.b@11>]");
+    assert_eq!(render(builder(), "#foreach($i in $list)$i#{end}").unwrap(), "<list@16>");
+    assert_eq!(render(builder(), "#macro(m $v)$v#end#m($missing)").unwrap(), "<missing@22>");
+    assert_eq!(render(builder(), "#set($x = $!quiet)[$!x]").unwrap(), "[]");
+    assert!(render(builder().strict(true), "#set($x = $keep)").is_err());
+    assert_eq!(render(builder().strict(true), "#set($x = $missing.name)$x").unwrap(), "<missing.name@11>");
+}
+
+#[test]
+pub fn include_test() {
+    let loader = MemoryResourceLoader::new();
+    loader.insert("parse/page.vm", "page $age");
+    loader.insert("include/page.vm", "raw $age");
+    loader.insert("secret.vm", "secret");
+    let engine = VelocityEngine::builder().loader(loader).include_handler(Includes).build();
+
+    let output = engine.render("#parse('page.vm') #include('page.vm') [#parse('secret.vm')#include('secret.vm')]", &mut context()).unwrap();
+    assert_eq!(output, "page 17 raw $age []");
+}
+
+#[test]
+pub fn method_exception_test() {
+    let builder = || VelocityEngine::builder().method_exception_handler(MethodFallback);
+    assert_eq!(render(builder(), "$age.toString().substring(5)").unwrap(), "17:index 5 out of range for length 2");
+    assert_eq!(render(builder(), "#set($s = $age.toString().substring(3))$s").unwrap(), "17:index 3 out of range for length 2");
+    assert_eq!(render(builder(), "$name.charAt(20)").unwrap(), "${name.charAt(20)}");
+    assert!(render(builder().strict(true), "$name.charAt(20)").is_err());
+}
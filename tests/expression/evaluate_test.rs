@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{render, VelocityError};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "Tom",
+        "title": "a && \"b\" || c",
+        "age": 17,
+        "price": 2.5,
+        "empty": "",
+        "nothing": null,
+        "list": [1, 2]
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn string_test() {
+    assert_eq!(eval("#if($name == \"Tom\")yes#{else}no#end"), "yes");
+    assert_eq!(eval("#if($name == 'Tom')yes#end"), "yes");
+    assert_eq!(eval("#if($name != \"Tom Cat\")yes#end"), "yes");
+    // 值中的空格、引号和运算符不会破坏表达式
+    assert_eq!(eval("#if($title == 'a && \"b\" || c')yes#end"), "yes");
+    assert_eq!(eval("#set($copy = $title)$copy"), "a && \"b\" || c");
+    assert_eq!(eval("#if(\"Tom\" < \"Tony\")yes#end"), "yes");
+}
+
+#[test]
+pub fn interpolation_test() {
+    assert_eq!(eval("#set($s = \"Hi $name, ${age}!\")$s"), "Hi Tom, 17!");
+    assert_eq!(eval("#set($s = 'Hi $name')$s"), "Hi $name");
+    assert_eq!(eval("#set($s = \"[$missing][$!missing]\")$s"), "[$missing][]");
+    assert_eq!(eval("#set($s = \"say \"\"hi\"\"\")$s"), "say \"hi\"");
+}
+
+#[test]
+pub fn number_test() {
+    assert_eq!(eval("#set($x = $age + 1)$x"), "18");
+    assert_eq!(eval("#set($x = $age / 2)$x"), "8");
+    assert_eq!(eval("#set($x = $age % 5 * -2)$x"), "-4");
+    assert_eq!(eval("#set($x = $price * 2)$x"), "5.0");
+    assert_eq!(eval("#set($x = ($age - 7) * $price)$x"), "25.0");
+    assert_eq!(eval("#set($x = $name + $age)$x"), "Tom17");
+    assert_eq!(eval("#if($age == 17.0 && $age == \"17\" && $age >= 10)yes#end"), "yes");
+}
+
+#[test]
+pub fn null_test() {
+    assert_eq!(eval("#if($nothing == null && $missing == null)yes#end"), "yes");
+    assert_eq!(eval("#if($name == null)yes#{else}no#end"), "no");
+    assert_eq!(eval("#if($nothing)yes#{else}no#end"), "no");
+    // 赋值为 null 时删除变量
+    assert_eq!(eval("#set($name = $missing)$name"), "${name}");
+}
+
+#[test]
+pub fn truthy_test() {
+    assert_eq!(eval("#if($name)yes#end"), "yes");
+    assert_eq!(eval("#if($empty || 0 || [])yes#{else}no#end"), "no");
+    assert_eq!(eval("#if(!$empty && $list)yes#end"), "yes");
+}
+
+#[test]
+pub fn collection_test() {
+    assert_eq!(eval("#set($l = [1, \"a\", $name, [true]])$l"), "[1,\"a\",\"Tom\",[true]]");
+    assert_eq!(eval("#set($m = {\"name\": $name, 'age': $age + 1})${m.name} ${m.age}"), "Tom 18");
+    assert_eq!(eval("#if($list == [1, 2])yes#end"), "yes");
+}
+
+#[test]
+pub fn invalid_test() {
+    // 计算出错的条件按 false 处理，#set 不修改变量
+    assert_eq!(eval("#if($name > 1)yes#{else}no#end"), "no");
+    assert_eq!(eval("#set($name = 1 / 0)$name"), "Tom");
+
+    // 语法错误在编译模板时报告
+    match render("#if($a ==)x#else y#end", &mut context()) {
+        Err(VelocityError::Syntax { message, location }) => {
+            assert_eq!(message, "invalid expression: unexpected end of expression");
+            assert_eq!(location.column, 10);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(render("#set($name = hello world)$name", &mut context()), Err(VelocityError::Syntax { .. })));
+    assert!(matches!(render("#foreach($i in [1,)#end", &mut context()), Err(VelocityError::Syntax { .. })));
+    assert!(matches!(render("#macro(m $a=(1)#end", &mut context()), Err(VelocityError::Syntax { .. })));
+}
+
+#[test]
+pub fn word_operator_test() {
+    assert_eq!(eval("#if($name eq 'Tom' and $age gt 10 and $age le 17)yes#end"), "yes");
+    assert_eq!(eval("#if(not $empty and ($age lt 10 or $age ge 17))yes#end"), "yes");
+    assert_eq!(eval("#if($name ne 'Tom' or not $list)yes#{else}no#end"), "no");
+    // 单词运算符必须是完整的单词
+    assert!(render("#if($age andx 1)yes#{else}no#end", &mut context()).is_err());
+}
+
+#[test]
+pub fn precedence_test() {
+    assert_eq!(eval("#set($x = 2 + 3 * 4 - 10 / 5 % 3)$x"), "12");
+    assert_eq!(eval("#set($x = (2 + 3) * -(4 - 1))$x"), "-15");
+    // && 的优先级高于 ||，比较运算高于逻辑运算
+    assert_eq!(eval("#if(true || false && false)yes#end"), "yes");
+    assert_eq!(eval("#if((true || false) && false)yes#{else}no#end"), "no");
+    assert_eq!(eval("#if(1 + 1 == 2 && !(1 > 2) == true)yes#end"), "yes");
+    assert_eq!(eval("#if(!$empty == true)yes#end"), "yes");
+}
@@ -1 +1,2 @@
-mod expression_test;
\ No newline at end of file
+mod expression_test;
+mod evaluate_test;
@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
 use velocity_template;
-use velocity_template::{read_file, render_from_object};
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{read_file, render, render_from_object, VelocityEngine, VelocityError};
 use velocity_template::token::token_parse::get_tokens;
 use crate::log_config;
+use crate::common;
 
 #[derive(Debug,Serialize,Deserialize)]
 struct User{
@@ -120,4 +123,69 @@ fn foreach_array_test(){
     }
 
 
-}
\ No newline at end of file
+}
+
+
+fn loop_context() -> HashMap<String, Value> {
+    common::context(json!({
+        "list": ["a", "b", "c"],
+        "rows": [{"index": "x", "cells": [1, 2]}, {"index": "y", "cells": [3]}]
+    }))
+}
+
+#[test]
+pub fn foreach_status_test() {
+    let template = "#foreach($item in $list)$foreach.index$foreach.count$foreach.first$foreach.last$foreach.hasNext #{end}|$!foreach.index";
+    let output = render(template, &mut loop_context()).unwrap();
+    assert_eq!(output, "01truefalsetrue 12falsefalsetrue 23falsetruefalse |");
+}
+
+#[test]
+pub fn foreach_parent_test() {
+    let template = "#foreach($row in $rows)#foreach($cell in $row.cells)$foreach.parent.index:$foreach.index=$cell #{end}#{end}[$!foreach.parent]";
+    let output = render(template, &mut loop_context()).unwrap();
+    assert_eq!(output, "0:0=1 0:1=2 1:0=3 []");
+    let template = "#foreach($row in $rows)#foreach($cell in $row.cells)$!foreach.parent.parent.index#{end}#{end}";
+    assert_eq!(render(template, &mut loop_context()).unwrap(), "");
+}
+
+#[test]
+pub fn element_loop_variables_test() {
+    // 默认不写入旧写法的循环变量，元素自己的 index 字段不会被遮住
+    let template = "#foreach($row in $rows)$row.index/$row.count/$foreach.count #{end}";
+    assert_eq!(render(template, &mut loop_context()).unwrap(), "x/${row.count}/1 y/${row.count}/2 ");
+
+    let engine = VelocityEngine::builder().element_loop_variables(true).build();
+    assert_eq!(engine.render(template, &mut loop_context()).unwrap(), "0/1/1 1/2/2 ");
+}
+
+#[test]
+pub fn foreach_else_test() {
+    let mut context = loop_context();
+    context.insert("empty".to_string(), json!([]));
+    context.insert("nothing".to_string(), Value::Null);
+
+    let template = "#foreach($c in $columns)$c#{else}none#end";
+    assert_eq!(render(template, &mut context).unwrap(), "none");
+    assert_eq!(render("#foreach($c in $empty)$c#{else}none[$!foreach]#end", &mut context).unwrap(), "none[]");
+    assert_eq!(render("#foreach($c in $nothing)$c#{else}none#end", &mut context).unwrap(), "none");
+    assert_eq!(render("#foreach($c in {})$c#{else}none#end", &mut context).unwrap(), "none");
+    assert_eq!(render("#foreach($c in $list)$c#{else}none#end", &mut context).unwrap(), "abc");
+
+    // 循环体中 #if 的 #else 不属于 #foreach
+    let template = "#foreach($c in $list)#if($foreach.first)$c#{else}-#end#{else}none#end";
+    assert_eq!(render(template, &mut context).unwrap(), "a--");
+
+    let template = "struct Row {\n#foreach($c in $empty)\n    $c,\n#else\n    // no columns\n#end\n}";
+    assert_eq!(render(template, &mut context).unwrap(), "struct Row {\n    // no columns\n}");
+}
+
+#[test]
+pub fn foreach_else_error_test() {
+    let error = render("#foreach($c in $list)#elseif(true)#end", &mut loop_context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::InvalidForeach { message, .. } if message == "unexpected #elseif inside #foreach"), "{:?}", error);
+    let error = render("#foreach($c in $list)#{else}#{else}#end", &mut loop_context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::InvalidForeach { message, .. } if message == "unexpected #else inside #foreach"), "{:?}", error);
+    assert!(matches!(render("#foreach($c in $list)#else", &mut loop_context()), Err(VelocityError::UnmatchedBlock { .. })));
+}
+
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{FileResourceLoader, MemoryResourceLoader, VelocityEngine, VelocityEngineBuilder, VelocityError};
+
+fn config() -> VelocityEngineBuilder {
+    VelocityEngine::builder().loader(FileResourceLoader::new("tests/include"))
+}
+
+fn render(template: &str, config: VelocityEngineBuilder) -> Result<String, VelocityError> {
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("name".to_string(), json!("Tom"));
+    config.build().render(template, &mut context)
+}
+
+#[test]
+pub fn parse_test() {
+    // #parse 在当前上下文中渲染，#include 原样输出，被引入模板可以使用已定义的宏
+    let output = render("#parse(\"page.vm\")", config()).unwrap();
+    assert_eq!(output, "Hello Tom\nraw $name #if(true)\n\n-- Tom --");
+
+    // 模板名称可以是变量
+    let output = render("#set($file = \"header.vm\")#parse($file)", config()).unwrap();
+    assert_eq!(output, "Hello Tom\n");
+
+    // #include 可以有多个参数
+    let output = render("#include(\"header.vm\", \"raw.txt\")", config()).unwrap();
+    assert_eq!(output, "Hello $name\nraw $name #if(true)\n");
+}
+
+#[test]
+pub fn cyclic_parse_test() {
+    match render("#parse(\"cycle_a.vm\")", config()) {
+        Err(VelocityError::Include { path, message, location }) => {
+            assert_eq!(path, "cycle_a.vm");
+            assert!(message.contains("cycle_a.vm -> cycle_b.vm -> cycle_a.vm"));
+            // 位置指向 cycle_b.vm 中的 #parse
+            assert_eq!(location.template, "cycle_b.vm");
+            assert_eq!(location.line, 2);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+pub fn max_depth_test() {
+    // chain1.vm -> chain2.vm -> chain3.vm -> chain4.vm
+    let output = render("#parse(\"chain1.vm\")", config().max_include_depth(4)).unwrap();
+    assert_eq!(output, "1\n2\n3\n4\n");
+
+    match render("#parse(\"chain1.vm\")", config().max_include_depth(3)) {
+        Err(VelocityError::Include { message, .. }) => assert!(message.contains("maximum #parse depth")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+pub fn missing_template_test() {
+    // 位置指向找不到模板的指令
+    for template in ["a\n #parse(\"missing.vm\")", "a\n #include(\"header.vm\", \"missing.vm\")"] {
+        match render(template, config()) {
+            Err(VelocityError::Include { path, message, location }) => {
+                assert_eq!(path, "missing.vm");
+                assert_eq!(message, "template not found");
+                assert_eq!((location.line, location.column), (2, 2));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    assert!(matches!(render("#parse()", config()), Err(VelocityError::Syntax { .. })));
+    assert!(matches!(render("#parse(\"a.vm\" \"b.vm\")", config()), Err(VelocityError::Syntax { .. })));
+}
+
+#[test]
+pub fn parsed_macro_test() {
+    // 宏定义在被引入的模板中，宏内容里原样输出的部分和出错位置都取自被引入的模板
+    let engine = |strict: bool| {
+        let loader = MemoryResourceLoader::new();
+        loader.insert("lib.vm", format!("{}#macro(m)#nothere(1)#end\n#macro(bad)\n$missing#end", " ".repeat(200)));
+        VelocityEngine::builder().loader(loader).strict(strict).build()
+    };
+    let output = engine(false).render("#parse('lib.vm')#m()", &mut HashMap::new()).unwrap();
+    assert_eq!(output, format!("{}\n#nothere(1)", " ".repeat(200)));
+
+    match engine(true).render("#parse('lib.vm')#bad()", &mut HashMap::new()) {
+        Err(VelocityError::UndefinedReference { name, location }) => {
+            assert_eq!(name, "missing");
+            assert_eq!(location.template, "lib.vm");
+            assert_eq!(location.line, 3);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
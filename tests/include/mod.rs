@@ -0,0 +1 @@
+pub mod include_test;
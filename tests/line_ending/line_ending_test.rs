@@ -0,0 +1,35 @@
+use velocity_template::render_default;
+
+const TEMPLATE: &str = "#set($name = \"Tom\")\nstart ## comment\n#if(true)\n  hello ${name}\n#end\n#foreach($i in $list)\n  $i\n#end\nend";
+
+#[test]
+pub fn lf_and_crlf_test() {
+    let lf = render_default(TEMPLATE).unwrap();
+    let crlf = render_default(&TEMPLATE.replace('\n', "\r\n")).unwrap();
+
+    assert!(!lf.contains('\r'));
+    assert_eq!(crlf.replace("\r\n", "\n"), lf);
+}
+
+#[test]
+pub fn mixed_line_ending_test() {
+    let lf = render_default(TEMPLATE).unwrap();
+
+    // 奇数行使用 \r\n，偶数行使用 \n
+    let mixed = TEMPLATE
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| if i % 2 == 0 { format!("{}\r\n", line) } else { format!("{}\n", line) })
+        .collect::<String>();
+    let mixed = mixed.strip_suffix("\r\n").or(mixed.strip_suffix('\n')).unwrap();
+
+    assert_eq!(render_default(mixed).unwrap().replace("\r\n", "\n"), lf);
+}
+
+#[test]
+pub fn line_comment_at_end_test() {
+    assert_eq!(render_default("abc ## comment").unwrap(), "abc ");
+    assert_eq!(render_default("abc ## comment\ndef").unwrap(), "abc def");
+    assert_eq!(render_default("abc ## comment\r\ndef").unwrap(), "abc def");
+    assert_eq!(render_default("##").unwrap(), "");
+}
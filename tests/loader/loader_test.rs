@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde_json::{json, Value};
+use velocity_template::{FileResourceLoader, MemoryResourceLoader, ResourceLoader, StaticResourceLoader, VelocityEngine, VelocityError};
+
+fn render(name: &str, loader: impl ResourceLoader + 'static) -> Result<String, VelocityError> {
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("name".to_string(), json!("Tom"));
+    let engine = VelocityEngine::builder().loader(loader).build();
+    engine.render_template(name, &mut context)
+}
+
+#[test]
+pub fn file_loader_test() {
+    // 按添加顺序在多个目录中查找
+    let loader = FileResourceLoader::new("tests/loader/first").add_path("tests/loader/second");
+    assert!(loader.exists("page.vm"));
+    assert!(loader.exists("other.vm"));
+    assert!(!loader.exists("missing.vm"));
+    assert!(loader.last_modified("page.vm").is_some());
+    assert!(loader.last_modified("missing.vm").is_none());
+
+    let loader = Arc::new(loader);
+    assert_eq!(render("page.vm", loader.clone()).unwrap(), "first Tom\n");
+    assert_eq!(render("other.vm", loader.clone()).unwrap(), "only in second\n");
+
+    let loader = Arc::new(FileResourceLoader::with_paths(["tests/loader/second", "tests/loader/first"]));
+    assert_eq!(render("page.vm", loader.clone()).unwrap(), "second Tom\n");
+    assert!(matches!(render("missing.vm", loader), Err(VelocityError::NotFound { name }) if name == "missing.vm"));
+}
+
+#[test]
+pub fn memory_loader_test() {
+    let loader = Arc::new(MemoryResourceLoader::new());
+    loader.insert("header.vm", "Hello $name");
+    loader.insert("page.vm", "#parse(\"header.vm\")!");
+    assert_eq!(render("page.vm", loader.clone()).unwrap(), "Hello Tom!");
+
+    // 更新模板后修改时间随之变化
+    let modified = loader.last_modified("header.vm").unwrap();
+    loader.insert("header.vm", "Hi $name");
+    assert!(loader.last_modified("header.vm").unwrap() > modified);
+    assert_eq!(render("page.vm", loader.clone()).unwrap(), "Hi Tom!");
+
+    loader.remove("header.vm");
+    assert!(!loader.exists("header.vm"));
+    assert!(matches!(render("page.vm", loader), Err(VelocityError::Include { path, .. }) if path == "header.vm"));
+
+    let templates = HashMap::from([("a.vm".to_string(), "a".to_string())]);
+    let loader = MemoryResourceLoader::from(templates);
+    assert_eq!(loader.load("a.vm").unwrap(), "a");
+}
+
+#[test]
+pub fn static_loader_test() {
+    static TEMPLATES: [(&str, &str); 2] = [
+        ("page.vm", include_str!("first/page.vm")),
+        ("layout.vm", "[#parse(\"page.vm\")]"),
+    ];
+    let loader = StaticResourceLoader::new(&TEMPLATES);
+    assert!(loader.exists("layout.vm"));
+    assert!(loader.last_modified("layout.vm").is_none());
+
+    assert_eq!(render("layout.vm", Arc::new(loader)).unwrap(), "[first Tom\n]");
+    assert!(matches!(render("missing.vm", Arc::new(loader)), Err(VelocityError::NotFound { .. })));
+}
+
+#[test]
+pub fn file_loader_root_test() {
+    // 模板名称不能跳出加载器的目录
+    let loader = FileResourceLoader::new("tests/loader/first");
+    assert!(loader.exists("./page.vm"));
+    assert!(loader.exists("sub/../page.vm"));
+    assert!(!loader.exists("../first/page.vm"));
+    assert!(!loader.exists("../second/other.vm"));
+    assert!(!loader.exists("../../../Cargo.toml"));
+    let absolute = std::fs::canonicalize("tests/loader/first/page.vm").unwrap();
+    assert!(!loader.exists(absolute.to_str().unwrap()));
+
+    let engine = VelocityEngine::default();
+    assert!(matches!(engine.render("#include(\"/etc/hostname\")", &mut HashMap::new()), Err(VelocityError::Include { .. })));
+    assert!(matches!(engine.render("#parse(\"../../etc/hostname\")", &mut HashMap::new()), Err(VelocityError::Include { .. })));
+}
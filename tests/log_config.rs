@@ -13,7 +13,8 @@ pub fn print_debug_log(){
                 record.args()
             )
         })
-        .init();
+        .try_init()
+        .ok();
 }
 
 pub fn print_info_log(){
@@ -28,5 +29,6 @@ pub fn print_info_log(){
                 record.args()
             )
         })
-        .init();
+        .try_init()
+        .ok();
 }
\ No newline at end of file
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{read_file, render, render_default, VelocityEngine, VelocityError};
+use crate::log_config;
+
+#[test]
+pub fn macro_file_test() {
+    log_config::print_debug_log();
+
+    let template = read_file("tests/macro_test/macro.vm").unwrap_or_default();
+    let output = render_default(&template).unwrap();
+    println!("------------------------------------\n{}", output);
+
+    assert_eq!(
+        output,
+        "public class User {\n    /** 主键 */\n    private Long id;\n    /**  */\n    private String name;\n    public Long getid() {\n        return this.id;\n    }\n}\n"
+    );
+}
+
+#[test]
+pub fn macro_args_test() {
+    let template = "#macro(hello $name $greeting=\"Hello\")$greeting, $name!#end#hello(\"Tom\") #hello(\"Jerry\", \"Hi\")";
+    assert_eq!(render_default(template).unwrap(), "Hello, Tom! Hi, Jerry!");
+
+    // 引用作为参数，foreach 的元素也可以传入
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("users".to_string(), json!([{"name": "a"}, {"name": "b"}]));
+    context.insert("title".to_string(), json!("users"));
+    let template = "#macro(item $user)<${user.name}>#end#macro(list $items $title)$title:#foreach($u in $items)#item($u)#end#end#list($users $title)";
+    assert_eq!(render(template, &mut context).unwrap(), "users:<a><b>");
+}
+
+#[test]
+pub fn macro_scope_test() {
+    // 参数只在宏内部可见，调用结束后恢复外部同名变量
+    let template = "#set($name = \"outer\")#macro(show $name)$name#end#show(\"inner\") $name";
+    assert_eq!(render_default(template).unwrap(), "inner outer");
+
+    // 没有传入的参数不会读到外部同名变量
+    let template = "#set($a = 1)#macro(show $a)[$!a]#end#show()";
+    assert_eq!(render_default(template).unwrap(), "[]");
+}
+
+#[test]
+pub fn block_macro_test() {
+    let template = "#macro(tag $name)<$name>$!bodyContent</$name>#end#@tag(\"b\")bold #@tag(\"i\")text#end#end";
+    assert_eq!(render_default(template).unwrap(), "<b>bold <i>text</i></b>");
+
+    // 普通调用没有 $bodyContent
+    assert_eq!(render_default("#macro(tag $name)<$name>$!bodyContent</$name>#end#tag(\"p\")").unwrap(), "<p></p>");
+}
+
+#[test]
+pub fn macro_recursion_test() {
+    let template = "#macro(countdown $n)$n#if($n > 0)#set($m = $n - 1) #countdown($m)#end#end#countdown(3)";
+    assert_eq!(render_default(template).unwrap(), "3 2 1 0");
+
+    let template = "#macro(forever)#forever()#end\n#forever()";
+    match render_default(template) {
+        Err(VelocityError::MacroCall { name, location, .. }) => {
+            assert_eq!(name, "forever");
+            assert_eq!(location.line, 1);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let engine = VelocityEngine::builder().max_macro_depth(2).build();
+    let template = "#macro(countdown $n)$n#if($n > 0)#set($m = $n - 1)#countdown($m)#end#end#countdown(5)";
+    assert!(engine.render(template, &mut HashMap::new()).is_err());
+}
+
+#[test]
+pub fn macro_error_test() {
+    // 未定义的宏原样输出
+    assert_eq!(render_default("#unknown($a 1) #@unknown()x#end").unwrap(), "#unknown($a 1) #@unknown()x#end");
+
+    assert!(matches!(render_default("#macro($a)#end"), Err(VelocityError::InvalidMacro { .. })));
+    assert!(matches!(render_default("#macro(m $a=)#end"), Err(VelocityError::InvalidMacro { .. })));
+    assert!(matches!(render_default("#macro(m $a)"), Err(VelocityError::UnmatchedBlock { .. })));
+    assert!(matches!(
+        render_default("#macro(m $a)$a#end#m(1 2)"),
+        Err(VelocityError::MacroCall { .. })
+    ));
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "  Hello World  ",
+        "word": "张三abc",
+        "list": ["a", "b", 3],
+        "empty": [],
+        "map": {"id": 1, "name": "Tom"}
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn string_test() {
+    assert_eq!(eval("$name.trim().toUpperCase()|$name.trim().toLowerCase()"), "HELLO WORLD|hello world");
+    assert_eq!(eval("$name.length() $word.length() ${word.substring(0,3)} $word.substring(2) $word.charAt(1)"), "15 5 张三a abc 三");
+    assert_eq!(eval("$name.trim().replace('o', '0') $word.indexOf('a') $word.lastIndexOf('x')"), "Hell0 W0rld 2 -1");
+    assert_eq!(eval("$word.startsWith('张') $word.endsWith(\"bc\") $word.contains('三a') $word.isEmpty()"), "true true true false");
+    assert_eq!(eval("$name.trim().split(' ') $word.concat(1) $word.equalsIgnoreCase('张三ABC')"), "[\"Hello\",\"World\"] 张三abc1 true");
+    assert_eq!(eval("$word.matches('[^a-z]+[a-z]+')"), "true");
+}
+
+#[test]
+pub fn list_test() {
+    assert_eq!(eval("$list.size() $empty.size() $list.isEmpty() $empty.isEmpty()"), "3 0 false true");
+    assert_eq!(eval("$list.get(2) $list.contains(3) $list.contains('c') $list.indexOf('b')"), "3 true false 1");
+}
+
+#[test]
+pub fn map_test() {
+    assert_eq!(eval("$map.keySet() $map.values() $map.size() $map.isEmpty()"), "[\"id\",\"name\"] [1,\"Tom\"] 2 false");
+    assert_eq!(eval("$map.get('name') $map.containsKey('id') $map.containsValue('Tom') $map.keySet().size()"), "Tom true true 2");
+}
+
+#[test]
+pub fn common_test() {
+    assert_eq!(eval("$map.id.toString().length() $list.toString() $map.id.equals(1.0)"), "1 [\"a\",\"b\",3] true");
+}
+
+#[test]
+pub fn expression_test() {
+    assert_eq!(eval("#if($word.startsWith('张') && $list.size() > 2)yes#end"), "yes");
+    assert_eq!(eval("#set($short = $name.trim().substring(0, 5).toUpperCase())$short"), "HELLO");
+    assert_eq!(eval("#set($s = \"${map.name.toUpperCase()}: $list.size()\")$s"), "TOM: 3");
+    assert_eq!(eval("#foreach($key in $map.keySet())$key=$map.get($key);#end"), "id=1;name=Tom;");
+}
+
+#[test]
+pub fn invalid_test() {
+    // 未知方法、参数错误或越界时按未定义处理
+    assert_eq!(eval("$word.foo()|$word.substring(9)|$word.length(1)|$!word.charAt(-1)"), "${word.foo()}|${word.substring(9)}|${word.length(1)}|");
+    assert_eq!(eval("#if($word.substring(9) == null)yes#{else}no#end"), "no");
+    assert_eq!(eval("$word.length("), "张三abc.length(");
+}
@@ -0,0 +1 @@
+pub mod method_test;
@@ -1,4 +1,5 @@
 pub mod log_config;
+pub mod common;
 
 pub mod parse_token_test;
 
@@ -11,4 +12,34 @@ pub mod set;
 
 pub mod comment;
 
-pub mod expression;
\ No newline at end of file
+pub mod expression;
+
+pub mod error;
+
+pub mod line_ending;
+
+pub mod space_gobbling;
+
+pub mod macro_test;
+
+pub mod include;
+
+pub mod loader;
+
+pub mod engine;
+
+pub mod template;
+
+pub mod cache;
+
+pub mod stream;
+pub mod property;
+pub mod method;
+pub mod quiet;
+pub mod escape;
+pub mod strict;
+pub mod event;
+pub mod auto_escape;
+pub mod scope;
+pub mod break_test;
+pub mod range;
@@ -1,14 +1,16 @@
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Number, Value};
-use velocity_template::{ read_file, render, render_from_object};
-use velocity_template::token::token_parse;
-use velocity_template::token::token_parse::Tokenizer;
+use velocity_template::{ read_file, render, render_default, render_from_object, SpaceGobbling, VelocityError};
+use velocity_template::expression::expr_parser;
+use velocity_template::token::{lexer, parser, token_parse};
+use velocity_template::token::lexer::Lexeme;
+use velocity_template::token::token_parse::{TemplateSource, Tokenizer};
 use crate::log_config;
 
 
 #[test]
-fn parse_position_test() {
+fn lexer_test() {
     log_config::print_debug_log();
 
     let template_path = "tests/entity.vm";
@@ -18,42 +20,90 @@ fn parse_position_test() {
         String::new() // 返回一个空字符串作为默认值
     };
 
-    println!("template: {:?}", template);
-    // println!("template: {:?}", &template[487..487+36]);
-
-    // // for x in template.chars().enumerate(){
-    // //     println!("{} {}",x.1,x.0 );
-    // // }
-    //
-    let result = token_parse::parse_position(&template, 0);
+    let source = TemplateSource::new(template_path, &template);
+    let lexemes = lexer::tokenize(&source).unwrap();
+
+    println!("lexemes: {:#?}", lexemes);
+
+    // 每个词法单元的区间都能在原始模板中找到
+    for lexeme in lexemes {
+        let span = match lexeme {
+            Lexeme::Text { span, .. }
+            | Lexeme::Reference { span, .. }
+            | Lexeme::EscapedReference { span, .. }
+            | Lexeme::Comment { span, .. }
+            | Lexeme::Directive { span, .. } => span,
+        };
+        println!("{:?}", &template[span.start..span.end]);
+    }
+}
 
-    println!("result: {:#?}", result);
 
-    if let Ok(list) = result {
-        for x in list {
-            println!("{:?}", &template[x.first_start..x.last_end]);
+#[test]
+fn parser_test() {
+    let template = "a #if($a == \"#end\") b #foreach($i in $list) ${i.name} #end #else c #end d";
+    let source = TemplateSource::new("parser_test", template);
+    let tokens = parser::parse(&source, SpaceGobbling::default()).unwrap();
+
+    assert_eq!(tokens.len(), 3);
+    match &tokens[1] {
+        Tokenizer::If { branches, span } => {
+            assert_eq!(branches.len(), 2);
+            assert_eq!(branches[0].condition.as_ref().unwrap().text, "$a == \"#end\"");
+            assert!(branches[1].condition.is_none());
+            assert_eq!(&template[span.start..span.end], &template[2..template.len() - 2]);
+
+            match &branches[0].children[1] {
+                Tokenizer::Foreach { element, collection, children, .. } => {
+                    assert_eq!(element, "i");
+                    assert_eq!(collection.text, "$list");
+                    assert_eq!(&template[collection.span.start..collection.span.end], "$list");
+                    assert!(matches!(&children[1], Tokenizer::Reference { name, .. } if name == "i.name"));
+                }
+                token => panic!("unexpected token: {:?}", token),
+            }
         }
+        token => panic!("unexpected token: {:?}", token),
     }
 }
 
-
 #[test]
-fn parse_position_token_test() {
-    log_config::print_debug_log();
-    let template_path = "tests/entity.vm";
-    let template = if let Ok(content) = read_file(template_path) {
-        content // 直接将 String 赋值给 template
-    } else {
-        String::new() // 返回一个空字符串作为默认值
-    };
-
-    println!("template: {:?}", template);
-    // log::debug!("14-34 {:?}",&template[49..307]);
-
-    let result = token_parse::parse_position(&template, 0);
-    println!("result:{:#?}",result);
+fn comment_and_string_test() {
+    // 注释中的 #end、字符串中的 #if 不会被当成指令
+    let template = "#* #end *#ab## #end\n#set($tag = \"#if(\")\n${tag}";
+    // `##` 注释连同换行符一起去掉，#set 单独占一行，它的换行符也去掉
+    assert_eq!(render_default(template).unwrap(), "ab#if(");
+
+    let template = "#{if}(true)yes#{else}no#{end}";
+    assert_eq!(render_default(template).unwrap(), "yes");
+}
 
+#[test]
+fn deep_nesting_test() {
+    let nested = |depth: usize| format!("{}ok{}", "#if(true)".repeat(depth), "#end".repeat(depth));
+
+    let template = nested(parser::MAX_NESTING_DEPTH);
+    let source = TemplateSource::new("deep_nesting_test", &template);
+    let tokens = parser::parse(&source, SpaceGobbling::default()).unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(render_default(&template).unwrap(), "ok");
+
+    // 超过层数限制时报错，而不是栈溢出
+    let template = nested(600);
+    let source = TemplateSource::new("deep_nesting_test", &template);
+    match parser::parse(&source, SpaceGobbling::default()) {
+        Err(VelocityError::Syntax { message, location }) => {
+            assert_eq!(message, "blocks are nested deeper than 64 levels");
+            assert_eq!(location.offset, "#if(true)".len() * parser::MAX_NESTING_DEPTH);
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
 
+    let expression = format!("{}1{}", "(".repeat(600), ")".repeat(600));
+    let err = expr_parser::parse(&expression).unwrap_err();
+    assert_eq!(err.message, "expression is nested deeper than 64 levels");
+    assert!(expr_parser::parse(&"!".repeat(600)).is_err());
+    assert!(expr_parser::parse(&format!("{}0{}", "$a[".repeat(600), "]".repeat(600))).is_err());
 }
 
 #[test]
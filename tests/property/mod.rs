@@ -0,0 +1 @@
+pub mod property_test;
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::render;
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "user": {
+            "name": "Tom",
+            "address": {"city": "Paris", "geo": {"lat": 48.85}},
+            "orders": [
+                {"id": 1, "items": [{"name": "book"}, {"name": "pen"}]},
+                {"id": 2, "items": []}
+            ],
+            "tags": {"first key": "a", "size": 7}
+        },
+        "list": [10, 20, 30],
+        "i": 1
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn object_test() {
+    assert_eq!(eval("${user.address.city} $user.address.geo.lat"), "Paris 48.85");
+    assert_eq!(eval("$user.name."), "Tom.");
+    assert_eq!(eval("$user.address.zip|$!user.address.zip|$missing.name"), "${user.address.zip}||${missing.name}");
+}
+
+#[test]
+pub fn index_test() {
+    assert_eq!(eval("$list[0] ${list[2]} $list[$i] $list.get(1) $list[ $i + 1 ]"), "10 30 20 20 30");
+    assert_eq!(eval("$user.orders[0].items[1].name $user.orders.get(0).items.get(0).name"), "pen book");
+    assert_eq!(eval("$list[5]|$list[-1]|$list[x]"), "${list[5]}|${list[-1]}|[10,20,30][x]");
+}
+
+#[test]
+pub fn map_test() {
+    assert_eq!(eval("$user.tags[\"first key\"] $user[\"address\"].city $user.tags.get('first key')"), "a Paris a");
+    // Map 中有 size 键时优先使用
+    assert_eq!(eval("$user.tags.size $user.address.size $list.size $user.orders[1].items.size"), "7 2 3 0");
+}
+
+#[test]
+pub fn expression_test() {
+    assert_eq!(eval("#if($user.address.city == 'Paris' && $list[1] > 15)yes#end"), "yes");
+    assert_eq!(eval("#set($first = $user.orders[0].items[0])$first.name"), "book");
+    assert_eq!(eval("#set($s = \"$user.name lives in ${user.address.city}\")$s"), "Tom lives in Paris");
+    assert_eq!(eval("#if($user.orders[1].items)yes#{else}no#end"), "no");
+}
+
+#[test]
+pub fn foreach_test() {
+    let template = "#foreach($order in $user.orders)$order.id:#foreach($item in $order.items)$item.name,#end;#end";
+    assert_eq!(eval(template), "1:book,pen,;2:;");
+}
+
+#[test]
+pub fn set_property_test() {
+    let mut context = context();
+    let output = render("#set($user.address.city = 'Lyon')#set($user.age = 18)$user.address.city $user.age", &mut context).unwrap();
+    assert_eq!(output, "Lyon 18");
+    assert_eq!(context["user"]["address"]["city"], json!("Lyon"));
+}
+
+#[test]
+pub fn macro_test() {
+    assert_eq!(eval("#macro(city $address)$address.city#end#city($user.address)"), "Paris");
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "Tom",
+        "nothing": null,
+        "user": {"address": null, "tags": ["a"]}
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn undefined_test() {
+    assert_eq!(eval("[$!missing][$!{missing}][$missing]"), "[][][${missing}]");
+    assert_eq!(eval("[$!name][$!{name}]"), "[Tom][Tom]");
+    assert_eq!(eval("$!{missing}text"), "text");
+}
+
+#[test]
+pub fn null_test() {
+    assert_eq!(eval("[$!nothing][$!{nothing}][$nothing]"), "[][][${nothing}]");
+    assert_eq!(eval("[$!user.address][$!user.address.city][$user.address]"), "[][][${user.address}]");
+}
+
+#[test]
+pub fn path_test() {
+    assert_eq!(eval("[$!user.tags[3]][$!{user.tags[0]}][$!missing.a.b]"), "[][a][]");
+}
+
+#[test]
+pub fn method_test() {
+    assert_eq!(eval("[$!nothing.length()][$!missing.toUpperCase()][$!name.toUpperCase()]"), "[][][TOM]");
+    assert_eq!(eval("[$!name.foo()][$!name.substring(10)]"), "[][]");
+}
+
+#[test]
+pub fn string_test() {
+    assert_eq!(eval("#set($s = \"[$!nothing][$!missing.name][$nothing]\")$s"), "[][][$nothing]");
+}
+
+#[test]
+pub fn literal_test() {
+    // `$!` 后面不是引用时按普通文本输出
+    assert_eq!(eval("$! $!{ $!1"), "$! $!{ $!1");
+}
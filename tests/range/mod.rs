@@ -0,0 +1 @@
+pub mod range_test;
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{render, VelocityEngine, VelocityError};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "n": 3,
+        "text": "4",
+        "word": "héllo",
+        "nothing": null
+    }))
+}
+
+fn eval(template: &str) -> String {
+    common::eval(template, context())
+}
+
+#[test]
+pub fn range_test() {
+    assert_eq!(eval("#foreach($i in [1..$n])$i,#{end}"), "1,2,3,");
+    assert_eq!(eval("#foreach($i in [$n..-1])$i,#{end}"), "3,2,1,0,-1,");
+    assert_eq!(eval("#foreach($i in [ 2 .. $text ])$i#{end}"), "234");
+    assert_eq!(eval("#foreach($i in [$n..$n])$i$foreach.last#{end}"), "3true");
+    assert_eq!(eval("#set($r = [0..$n - 1])$r $r.size()"), "[0,1,2] 3");
+}
+
+#[test]
+pub fn invalid_range_test() {
+    assert_eq!(eval("#foreach($i in [1..$word])$i#{end}|"), "|");
+    assert_eq!(eval("#foreach($i in [1..$missing])$i#{end}|"), "|");
+    let engine = VelocityEngine::builder().strict(true).build();
+    let error = engine.render("#foreach($i in [1..2.5])#end", &mut context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::Expression { message, .. } if message == "range bounds must be integers, found number"), "{:?}", error);
+}
+
+#[test]
+pub fn range_limit_test() {
+    // #foreach 按上下界逐个循环，不生成数组
+    assert_eq!(eval("#foreach($i in [0..99999999999])$i#if($i == 2)#break#end#{end}|"), "012|");
+    assert_eq!(eval("#foreach($i in [-9223372036854775807..9223372036854775807])$foreach.hasNext#break#{end}"), "true");
+
+    let engine = VelocityEngine::builder().max_range_length(3).build();
+    assert_eq!(engine.render("#set($r = [1..3])$r", &mut context()).unwrap(), "[1,2,3]");
+    // 范围计算为数组时超出限制，非严格模式下也返回错误
+    let error = engine.render("#set($r = [0..$n])$r", &mut context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::Expression { message, .. } if message == "range [0..3] has 4 items, more than the maximum of 3"), "{:?}", error);
+    assert!(render("#set($r = [0..99999999999])", &mut context()).is_err());
+}
+
+#[test]
+pub fn literal_test() {
+    assert_eq!(eval("#foreach($s in [\"a\", 'b', $n])$s#{end}"), "ab3");
+    assert_eq!(eval("#foreach($e in {\"x\": 1, \"y\": $n})$e.key=$e.value #{end}"), "x=1 y=3 ");
+    assert_eq!(eval("#foreach($e in [])$e#{end}|"), "|");
+}
+
+#[test]
+pub fn string_test() {
+    assert_eq!(eval("#foreach($c in $word)[$c]#{end}"), "[h][é][l][l][o]");
+    assert_eq!(eval("#foreach($c in 'ab')$c$foreach.index#{end}"), "a0b1");
+}
+
+#[test]
+pub fn null_test() {
+    assert_eq!(eval("#foreach($i in $nothing)$i#{end}|"), "|");
+    assert_eq!(eval("#foreach($i in $n)$i#{end}|"), "|");
+
+    let engine = VelocityEngine::builder().strict(true).build();
+    assert_eq!(engine.render("#foreach($i in $nothing)$i#{end}|", &mut context()).unwrap(), "|");
+    let error = engine.render("#foreach($i in $n)#end", &mut context()).unwrap_err();
+    assert!(matches!(&error, VelocityError::InvalidForeach { message, .. } if message == "cannot iterate over number"), "{:?}", error);
+}
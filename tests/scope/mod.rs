@@ -0,0 +1 @@
+pub mod scope_test;
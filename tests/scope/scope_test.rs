@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{render, Context, FrameKind, SetScope, Variables, VelocityEngine};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "list": ["a", "b"],
+        "map": {"k": "v"}
+    }))
+}
+
+#[test]
+pub fn loop_variable_test() {
+    let mut context = context();
+    let output = render("#foreach($item in $list)$item$foreach.index#{end}|$item|$!foreach.index", &mut context).unwrap();
+    assert_eq!(output, "a0b1|${item}|");
+    let engine = VelocityEngine::builder().element_loop_variables(true).build();
+    let output = engine.render("#foreach($item in $list)$item$item.index#{end}|$item.index|$!item.count", &mut context).unwrap();
+    assert_eq!(output, "a0b1|${item.index}|");
+    let output = render("#foreach($entry in $map)$entry.key=$entry.value#{end}|$!entry.key", &mut context).unwrap();
+    assert_eq!(output, "k=v|");
+    assert_eq!(context, self::context());
+
+    // 外层的同名变量在循环结束后恢复
+    context.insert("item".to_string(), json!("outer"));
+    let output = render("#foreach($item in $list)$item#{end}|$item", &mut context).unwrap();
+    assert_eq!(output, "ab|outer");
+}
+
+#[test]
+pub fn nested_loop_test() {
+    let mut context = context();
+    let template = "#foreach($x in $list)#foreach($y in $list)$x$foreach.index #{end}[$y]#{end}";
+    assert_eq!(render(template, &mut context).unwrap(), "a0 a1 [${y}]b0 b1 [${y}]");
+}
+
+#[test]
+pub fn set_in_loop_test() {
+    let mut context = context();
+    let output = render("#foreach($item in $list)#set($last = $item)#set($item = 'x')$item#{end}|$last|$!item", &mut context).unwrap();
+    assert_eq!(output, "xx|b|");
+    assert_eq!(context.get("last"), Some(&json!("b")));
+    assert!(!context.contains_key("item"));
+}
+
+#[test]
+pub fn macro_test() {
+    let template = "#macro(show $name)#set($name = \"$name!\")#set($seen = true)$name#end#show('a') #show()|$name|$seen";
+    let mut context = context();
+    context.insert("name".to_string(), json!("outer"));
+    let output = render(template, &mut context).unwrap();
+    assert_eq!(output, "a! $name!|outer|true");
+    assert_eq!(context.get("name"), Some(&json!("outer")));
+
+    let engine = VelocityEngine::builder().set_scope(SetScope::Local).build();
+    let mut context = self::context();
+    let output = engine.render("#macro(m)#set($seen = true)$seen#end#m()|$!seen #set($top = 1)$top", &mut context).unwrap();
+    assert_eq!(output, "true| 1");
+    assert!(!context.contains_key("seen"));
+    assert_eq!(context.get("top"), Some(&json!(1)));
+}
+
+#[test]
+pub fn tool_test() {
+    let engine = VelocityEngine::builder().tool("app", json!({"name": "demo"})).build();
+    let mut context = context();
+    assert_eq!(engine.render("$app.name #set($app = 'mine')$app", &mut context).unwrap(), "demo mine");
+    assert_eq!(context.get("app"), Some(&json!("mine")));
+    context.remove("app");
+    assert_eq!(engine.render("$app.name", &mut context).unwrap(), "demo");
+    assert!(!context.contains_key("app"));
+}
+
+#[test]
+pub fn context_test() {
+    let globals = HashMap::from([("g".to_string(), json!(1))]);
+    let mut template = HashMap::from([("t".to_string(), json!(2))]);
+    let mut context = Context::new(&globals, &mut template, SetScope::Global);
+
+    context.push(FrameKind::Foreach);
+    context.define("g", json!("local"));
+    context.set("g", json!("changed"));
+    context.set("t", json!(3));
+    assert_eq!(context.get("g"), Some(&json!("changed")));
+    context.pop();
+
+    assert_eq!(context.get("g"), Some(&json!(1)));
+    assert_eq!(context.get("t"), Some(&json!(3)));
+    context.set("t", Value::Null);
+    assert_eq!(context.get("t"), None);
+}
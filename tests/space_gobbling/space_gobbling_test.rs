@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{SpaceGobbling, VelocityEngine};
+
+const TEMPLATE: &str = "<ul>\n  #set($name = \"Tom\")\n  #foreach($i in $list)\n    #if(${i.show})\n      <li>$i.name $name</li>\n    #end\n  #end\n</ul>";
+
+fn render_mode(template: &str, mode: SpaceGobbling) -> String {
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("list".to_string(), json!([{"name": "a", "show": true}, {"name": "b", "show": true}]));
+    let engine = VelocityEngine::builder().space_gobbling(mode).build();
+    engine.render(template, &mut context).unwrap()
+}
+
+#[test]
+pub fn none_test() {
+    assert_eq!(
+        render_mode(TEMPLATE, SpaceGobbling::None),
+        "<ul>\n  \n  \n    \n      <li>a Tom</li>\n    \n  \n    \n      <li>b Tom</li>\n    \n  \n</ul>"
+    );
+}
+
+#[test]
+pub fn bc_test() {
+    assert_eq!(
+        render_mode(TEMPLATE, SpaceGobbling::Bc),
+        "<ul>\n            <li>a Tom</li>\n                <li>b Tom</li>\n      </ul>"
+    );
+}
+
+#[test]
+pub fn lines_test() {
+    assert_eq!(
+        render_mode(TEMPLATE, SpaceGobbling::Lines),
+        "<ul>\n      <li>a Tom</li>\n      <li>b Tom</li>\n</ul>"
+    );
+    // 默认使用 lines
+    assert_eq!(render_mode(TEMPLATE, SpaceGobbling::default()), render_mode(TEMPLATE, SpaceGobbling::Lines));
+}
+
+#[test]
+pub fn structured_test() {
+    assert_eq!(
+        render_mode(TEMPLATE, SpaceGobbling::Structured),
+        "<ul>\n  <li>a Tom</li>\n  <li>b Tom</li>\n</ul>"
+    );
+}
+
+#[test]
+pub fn inline_directive_test() {
+    // 与其他内容同一行的指令不会去掉空白
+    let template = "a #if(true) b #end c\n";
+    assert_eq!(render_mode(template, SpaceGobbling::Lines), "a  b  c\n");
+    assert_eq!(render_mode(template, SpaceGobbling::Structured), "a  b  c\n");
+}
+
+#[test]
+pub fn comment_line_test() {
+    // 单独占一行的注释与指令一样，去掉缩进和换行符
+    for mode in [SpaceGobbling::Lines, SpaceGobbling::Structured] {
+        assert_eq!(render_mode("a\n  ## c\nb", mode), "a\nb");
+        assert_eq!(render_mode("a\n  #* c *#\nb", mode), "a\nb");
+        assert_eq!(render_mode("a\n  #* c\n  d *#  \nb", mode), "a\nb");
+        assert_eq!(render_mode("a\n  ## c\n  ## d\n  #if(true)\nb\n  #end\n", mode), "a\nb\n");
+    }
+    assert_eq!(render_mode("#if(true)\n    ## c\n    x\n#end\n", SpaceGobbling::Structured), "x\n");
+    // 与其他内容同一行的注释不会去掉空白
+    assert_eq!(render_mode("a ## c\nb", SpaceGobbling::Lines), "a b");
+    assert_eq!(render_mode("a #* c *# b\n", SpaceGobbling::Lines), "a  b\n");
+    assert_eq!(render_mode("a\n  ## c\nb", SpaceGobbling::None), "a\n  b");
+}
+
+#[test]
+pub fn crlf_test() {
+    let crlf = render_mode(&TEMPLATE.replace('\n', "\r\n"), SpaceGobbling::Lines);
+    assert_eq!(crlf.replace("\r\n", "\n"), render_mode(TEMPLATE, SpaceGobbling::Lines));
+}
+
+#[test]
+pub fn from_str_test() {
+    assert_eq!("none".parse::<SpaceGobbling>().unwrap(), SpaceGobbling::None);
+    assert_eq!("BC".parse::<SpaceGobbling>().unwrap(), SpaceGobbling::Bc);
+    assert_eq!("lines".parse::<SpaceGobbling>().unwrap(), SpaceGobbling::Lines);
+    assert_eq!("structured".parse::<SpaceGobbling>().unwrap(), SpaceGobbling::Structured);
+    assert!("unknown".parse::<SpaceGobbling>().is_err());
+}
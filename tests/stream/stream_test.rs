@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::io;
+use serde_json::{json, Value};
+use velocity_template::{VelocityEngine, VelocityError};
+use crate::common;
+
+const TEMPLATE: &str = "#macro(row $v)<td>$v</td>#end\n#foreach($i in $list)\n#row($i)\n#end\n";
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "list": (0..1000).collect::<Vec<i32>>()
+    }))
+}
+
+/// 记录每次写入的 writer
+#[derive(Default)]
+struct ChunkWriter {
+    chunks: Vec<Vec<u8>>,
+}
+
+impl io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunks.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 写入若干字节后失败的 writer
+struct FailingWriter {
+    remaining: usize,
+}
+
+impl io::Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.remaining < buf.len() {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+        }
+        self.remaining -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+pub fn render_to_test() {
+    let template = VelocityEngine::default().compile(TEMPLATE).unwrap();
+    let expected = template.render(&context()).unwrap();
+    assert!(expected.starts_with("\n<td>0</td><td>1</td><td>2</td>"));
+
+    let mut bytes: Vec<u8> = Vec::new();
+    template.render_to(&context(), &mut bytes).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+
+    // 输出是分段写入的，没有先拼接成完整字符串
+    let mut writer = ChunkWriter::default();
+    template.render_to(&context(), &mut writer).unwrap();
+    assert!(writer.chunks.len() > 1000);
+    assert_eq!(writer.chunks.concat(), expected.as_bytes());
+
+    let mut output = String::new();
+    template.render_fmt(&context(), &mut output).unwrap();
+    assert_eq!(output, expected);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    VelocityEngine::default().render_to(TEMPLATE, &mut context(), &mut bytes).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+}
+
+#[test]
+pub fn write_error_test() {
+    let template = VelocityEngine::default().compile(TEMPLATE).unwrap();
+    let mut writer = FailingWriter { remaining: 100 };
+    match template.render_to(&context(), &mut writer) {
+        Err(VelocityError::Write { source }) => assert_eq!(source.kind(), io::ErrorKind::BrokenPipe),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
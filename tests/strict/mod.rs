@@ -0,0 +1 @@
+pub mod strict_test;
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use velocity_template::{VelocityEngine, VelocityError};
+use crate::common;
+
+fn context() -> HashMap<String, Value> {
+    common::context(json!({
+        "name": "Tom",
+        "flag": true,
+        "nothing": null,
+        "user": {"address": null, "tags": ["a"]}
+    }))
+}
+
+fn strict(template: &str) -> Result<String, VelocityError> {
+    let engine = VelocityEngine::builder().strict(true).build();
+    engine.render(template, &mut context())
+}
+
+#[test]
+pub fn defined_test() {
+    let template = "$name #if($flag)yes#{end} #set($n = $user.tags.size())$n [$!missing][$!user.address.city]";
+    assert_eq!(strict(template).unwrap(), "Tom yes 1 [][]");
+}
+
+#[test]
+pub fn undefined_reference_test() {
+    let error = strict("line 1\nhello $missing").unwrap_err();
+    match &error {
+        VelocityError::UndefinedReference { name, location } => {
+            assert_eq!(name, "missing");
+            assert_eq!(location.line, 2);
+            assert_eq!(location.column, 7);
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+
+    let error = strict("$user.name").unwrap_err();
+    assert!(matches!(&error, VelocityError::UndefinedReference { name, .. } if name == "user.name"), "{:?}", error);
+    let error = strict("#set($a = $missing + 1)").unwrap_err();
+    assert!(matches!(&error, VelocityError::UndefinedReference { name, .. } if name == "missing"), "{:?}", error);
+    assert!(strict("#if($missing)yes#end").is_err());
+    assert!(strict("$name.foo()").is_err());
+}
+
+#[test]
+pub fn null_test() {
+    let error = strict("$user.address.city").unwrap_err();
+    match &error {
+        VelocityError::Expression { message, location } => {
+            assert_eq!(message, "null value in $user.address.city");
+            assert_eq!(location.as_ref().unwrap().column, 1);
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+    assert!(strict("$nothing").is_err());
+    assert_eq!(strict("#if($nothing == $null)yes#end").unwrap_err().to_string().lines().next().unwrap(), "undefined reference $null at <inline>:1:5");
+}
+
+#[test]
+pub fn condition_test() {
+    let error = strict("#if(false)\n#elseif($name)#end").unwrap_err();
+    match &error {
+        VelocityError::Expression { message, location } => {
+            assert_eq!(message, "condition must be a boolean, found string");
+            let location = location.as_ref().unwrap();
+            assert_eq!((location.line, location.column), (2, 9));
+        }
+        _ => panic!("unexpected error: {:?}", error),
+    }
+    assert_eq!(strict("#if($name == 'Tom' && !$user.tags.isEmpty())yes#end").unwrap(), "yes");
+}
+
+#[test]
+pub fn invalid_expression_test() {
+    let error = strict("#set($a = 1 / 0)").unwrap_err();
+    assert!(matches!(&error, VelocityError::Expression { .. }), "{:?}", error);
+    assert!(strict("#if($name > 1)#end").is_err());
+}
+
+#[test]
+pub fn lenient_test() {
+    let mut context = context();
+    let output = VelocityEngine::default().render("$missing #if($name)yes#end #set($a = 1 / 0)$!a", &mut context).unwrap();
+    assert_eq!(output, "${missing} yes ");
+}
@@ -0,0 +1 @@
+pub mod template_test;
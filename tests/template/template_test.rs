@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use serde::Serialize;
+use serde_json::{json, Value};
+use velocity_template::{MemoryResourceLoader, Template, VelocityEngine, VelocityError};
+
+#[test]
+pub fn compile_test() {
+    let engine = VelocityEngine::default();
+    let template = engine.compile("#set($greeting = \"Hello\")$greeting $name").unwrap();
+    assert_eq!(template.name(), "<inline>");
+    assert_eq!(template.source(), "#set($greeting = \"Hello\")$greeting $name");
+
+    let mut context: HashMap<String, Value> = HashMap::new();
+    for name in ["Tom", "Jerry"] {
+        context.insert("name".to_string(), json!(name));
+        assert_eq!(template.render(&context).unwrap(), format!("Hello {}", name));
+    }
+    // render 不会修改传入的上下文，render_mut 会保留 #set 的结果
+    assert!(!context.contains_key("greeting"));
+    template.render_mut(&mut context).unwrap();
+    assert_eq!(context["greeting"], json!("Hello"));
+
+    #[derive(Serialize)]
+    struct User {
+        name: String,
+    }
+    assert_eq!(template.render_object(&User { name: "Spike".to_string() }).unwrap(), "Hello Spike");
+}
+
+#[test]
+pub fn compile_error_test() {
+    let engine = VelocityEngine::default();
+    assert!(matches!(engine.compile("#if(true)"), Err(VelocityError::UnmatchedBlock { .. })));
+    assert!(matches!(engine.get_template("missing.vm"), Err(VelocityError::NotFound { .. })));
+}
+
+#[test]
+pub fn get_template_test() {
+    let loader = Arc::new(MemoryResourceLoader::new());
+    loader.insert("header.vm", "== $title ==\n");
+    loader.insert("page.vm", "#parse(\"header.vm\")\n$body");
+    let engine = VelocityEngine::builder().loader(loader).build();
+
+    let template = engine.get_template("page.vm").unwrap();
+    assert_eq!(template.name(), "page.vm");
+
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("title".to_string(), json!("Title"));
+    context.insert("body".to_string(), json!("Body"));
+    assert_eq!(template.render(&context).unwrap(), "== Title ==\nBody");
+}
+
+#[test]
+pub fn shared_template_test() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Template>();
+
+    let template = VelocityEngine::default().compile("#foreach($i in $list)$i#end").unwrap();
+    let handles: Vec<_> = (0..4)
+        .map(|n| {
+            let template = template.clone();
+            thread::spawn(move || {
+                let mut context: HashMap<String, Value> = HashMap::new();
+                context.insert("list".to_string(), json!((0..n).collect::<Vec<i32>>()));
+                template.render(&context).unwrap()
+            })
+        })
+        .collect();
+    let outputs: Vec<String> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(outputs, vec!["", "0", "01", "012"]);
+}
+
+#[test]
+pub fn read_only_context_test() {
+    // render 不复制 context：#set 写入单独的一层，修改、删除已有变量都不影响传入的 context
+    let template = VelocityEngine::default()
+        .compile("#set($user.name = \"Jerry\")#set($age = $null)$user.name $!age #foreach($i in [1])$user.name#end")
+        .unwrap();
+    let mut context: HashMap<String, Value> = HashMap::new();
+    context.insert("user".to_string(), json!({"name": "Tom"}));
+    context.insert("age".to_string(), json!(3));
+    assert_eq!(template.render(&context).unwrap(), "Jerry  Jerry");
+    assert_eq!(context["user"], json!({"name": "Tom"}));
+    assert_eq!(context["age"], json!(3));
+
+    let mut output = Vec::new();
+    template.render_to(&context, &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "Jerry  Jerry");
+}